@@ -0,0 +1,120 @@
+// L2缓存控制器的初始化、ECC错误统计和缓存维护
+//
+// 启动时打开L2缓存的所有路，并在第ECC_HART个核的M态处理L2缓存的ECC错误中断：
+// 每种错误记录次数和最近一次出错的地址，操作系统通过厂商SBI扩展读取，见vendor_extension模块。
+//
+// 同一个扩展也让操作系统按物理地址写回并作废L2缓存，给和缓存不一致的外设使用；
+// 地址范围必须在设备树给出的内存中，不能借此访问其它设备。写回期间M态不响应中断，
+// 一次最多写回L2缓存容量大小的范围（FU740是2MiB，32768次Flush64），更大的范围由操作系统分多次调用。
+use crate::console::println;
+use crate::external_interrupt;
+use crate::peripheral::EccError;
+use crate::platform::{self, L2_CACHE_ECC_SOURCES};
+use crate::util::AmoMutex;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// 处理ECC错误中断的核；第0个核没有S态，操作系统通常不启动它，由第1个核处理
+const ECC_HART: usize = 1;
+/// ECC错误中断的优先级，高于操作系统可能使用的默认优先级
+const ECC_PRIORITY: u32 = 7;
+
+// 每种ECC错误的次数和最近一次出错的地址，下标是EccError
+static ECC_COUNTS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+static ECC_LAST_ADDRESSES: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+// 设备树给出的内存，只有其中的地址可以按地址写回缓存
+static MEMORY: AmoMutex<Option<Range<usize>>> = AmoMutex::new(None);
+
+/// 打开L2缓存的所有路，登记ECC错误中断；在第0个核唤醒其它核之前调用
+///
+/// memory是设备树给出的内存，没有时不能按地址写回缓存。
+pub fn init(memory: Option<Range<usize>>) {
+    let l2_cache = match platform::l2_cache() {
+        Some(l2_cache) => l2_cache,
+        None => return,
+    };
+    *MEMORY.lock() = memory;
+    let config = l2_cache.config();
+    l2_cache.enable_all_ways();
+    println!(
+        "[rustsbi] L2 cache: {} banks, {} ways, {} sets, {} bytes per block, {} ways enabled",
+        config.banks,
+        config.ways,
+        config.sets,
+        config.block_bytes,
+        l2_cache.enabled_ways()
+    );
+    for source in L2_CACHE_ECC_SOURCES {
+        external_interrupt::register(source, ECC_HART, ECC_PRIORITY, handle_ecc_interrupt);
+    }
+}
+
+fn handle_ecc_interrupt(source: u32) {
+    let error = match L2_CACHE_ECC_SOURCES.iter().position(|s| *s == source) {
+        Some(index) => EccError::ALL[index],
+        None => return,
+    };
+    // 登记了中断源的平台一定有L2缓存控制器
+    let (address, count) = platform::l2_cache().unwrap().take_ecc_error(error);
+    // 计数寄存器是累计值，直接保存，不能累加
+    ECC_COUNTS[error as usize].store(count as u64, Ordering::Relaxed);
+    ECC_LAST_ADDRESSES[error as usize].store(address, Ordering::Relaxed);
+    println!(
+        "[rustsbi] warning: L2 cache ecc error {:?} at address {:#x}, count {}",
+        error, address, count
+    );
+}
+
+/// 这种ECC错误发生的次数
+pub fn ecc_count(error: EccError) -> u64 {
+    ECC_COUNTS[error as usize].load(Ordering::Relaxed)
+}
+
+/// 这种ECC错误最近一次出错的地址，没有发生过时是0
+pub fn ecc_last_address(error: EccError) -> u64 {
+    ECC_LAST_ADDRESSES[error as usize].load(Ordering::Relaxed)
+}
+
+/// 按物理地址写回缓存时的错误
+#[derive(Debug)]
+pub enum FlushError {
+    /// 平台没有L2缓存控制器
+    NotSupported,
+    /// 地址范围不全在内存中
+    InvalidAddress,
+    /// 长度超过L2缓存的容量
+    TooLarge,
+}
+
+/// 写回并作废[address, address + size)所在的L2缓存块，size不能超过L2缓存的容量
+pub fn flush_range(address: usize, size: usize) -> Result<(), FlushError> {
+    let l2_cache = platform::l2_cache().ok_or(FlushError::NotSupported)?;
+    let end = address
+        .checked_add(size)
+        .ok_or(FlushError::InvalidAddress)?;
+    let in_memory = match MEMORY.lock().as_ref() {
+        Some(memory) => memory.start <= address && end <= memory.end,
+        None => false,
+    };
+    if !in_memory {
+        return Err(FlushError::InvalidAddress);
+    }
+    let config = l2_cache.config();
+    if size > config.capacity() {
+        return Err(FlushError::TooLarge);
+    }
+    if size == 0 {
+        return Ok(());
+    }
+    let block_bytes = config.block_bytes as usize;
+    // 写回之前完成所有的访存，写回完成之后才能继续访存
+    unsafe { core::arch::asm!("fence rw, o") };
+    let mut block = address & !(block_bytes - 1);
+    while block < end {
+        l2_cache.flush64(block as u64);
+        block += block_bytes;
+    }
+    unsafe { core::arch::asm!("fence o, rw") };
+    Ok(())
+}
@@ -1,31 +1,220 @@
 use crate::peripheral::Uart;
 use crate::util::AmoMutex;
 use core::fmt;
-use embedded_hal::serial::Write;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use embedded_hal::serial::{Read, Write};
 
-static STDOUT: AmoMutex<Option<Uart>> = AmoMutex::new(None);
+// 每个UART对应一个控制台；固件日志和SBI控制台使用同一个UART时共享发送队列，保证输出顺序
+static CONSOLES: AmoMutex<[Option<Console>; 2]> = AmoMutex::new([None, None]);
+// 固件日志使用的UART编号，usize::MAX表示还没有初始化
+static STDOUT: AtomicUsize = AtomicUsize::new(usize::MAX);
+// 软件发送队列中是否还有数据没有写入硬件
+static TX_PENDING: AtomicBool = AtomicBool::new(false);
 
 pub fn init_stdout(uart: Uart) {
-    let mut lock = STDOUT.lock();
-    *lock = Some(uart);
+    register(uart);
+    STDOUT.store(uart.index(), Ordering::Release);
+}
+
+fn register(uart: Uart) {
+    let mut lock = CONSOLES.lock();
+    let console = &mut lock[uart.index()];
+    if console.is_none() {
+        *console = Some(Console::new(uart));
+    }
+    drop(lock);
+}
+
+/// 把软件队列中的数据尽量写入硬件发送队列，不等待
+///
+/// 没有使用发送中断，软件队列依靠写入、flush和这个函数推进；执行循环在每次处理异常后调用它。
+#[inline]
+pub fn poll() {
+    if TX_PENDING.load(Ordering::Relaxed) {
+        let mut lock = CONSOLES.lock();
+        let mut pending = false;
+        for console in lock.iter_mut().flatten() {
+            pending |= !console.drain();
+        }
+        TX_PENDING.store(pending, Ordering::Relaxed);
+        drop(lock);
+    }
+}
+
+/// 软件发送队列中是否还有数据没有写入硬件
+#[inline]
+pub fn pending() -> bool {
+    TX_PENDING.load(Ordering::Relaxed)
+}
+
+/// 等待所有控制台的数据都从串口发出，关机或者出错停机前调用
+pub fn flush() {
+    let mut lock = CONSOLES.lock();
+    for console in lock.iter_mut().flatten() {
+        console.flush();
+    }
+    TX_PENDING.store(false, Ordering::Relaxed);
     drop(lock);
 }
 
-impl fmt::Write for Uart {
+struct Console {
+    uart: Uart,
+    #[cfg(feature = "uart-tx-fifo")]
+    fifo: TxFifo,
+}
+
+impl Console {
+    const fn new(uart: Uart) -> Self {
+        Console {
+            uart,
+            #[cfg(feature = "uart-tx-fifo")]
+            fifo: TxFifo::new(),
+        }
+    }
+
+    // 硬件发送队列满时，放进软件队列后立即返回；软件队列也满时才等待
+    #[cfg(feature = "uart-tx-fifo")]
+    fn write_byte(&mut self, byte: u8) {
+        if self.drain() && self.uart.write(byte).is_ok() {
+            return;
+        }
+        while !self.fifo.push(byte) {
+            self.drain();
+            core::hint::spin_loop();
+        }
+        TX_PENDING.store(true, Ordering::Relaxed);
+    }
+
+    #[cfg(not(feature = "uart-tx-fifo"))]
+    fn write_byte(&mut self, byte: u8) {
+        nb::block!(self.uart.write(byte)).ok();
+    }
+
+    // 返回软件队列是否已经清空
+    #[cfg(feature = "uart-tx-fifo")]
+    fn drain(&mut self) -> bool {
+        while let Some(byte) = self.fifo.front() {
+            if self.uart.write(byte).is_err() {
+                return false;
+            }
+            self.fifo.pop();
+        }
+        true
+    }
+
+    #[cfg(not(feature = "uart-tx-fifo"))]
+    fn drain(&mut self) -> bool {
+        true
+    }
+
+    fn flush(&mut self) {
+        while !self.drain() {
+            core::hint::spin_loop();
+        }
+        nb::block!(self.uart.flush()).ok();
+    }
+}
+
+impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for byte in s.as_bytes() {
-            nb::block!(self.write(*byte)).ok(); // todo: 为了极致性能，未来添加水标设置
+            self.write_byte(*byte);
         }
-        nb::block!(self.flush()).ok(); // todo: 这行会影响输出
         Ok(())
     }
 }
 
+#[cfg(feature = "uart-tx-fifo")]
+const TX_FIFO_SIZE: usize = 4096;
+
+// 发送用的软件环形队列
+#[cfg(feature = "uart-tx-fifo")]
+struct TxFifo {
+    buf: [u8; TX_FIFO_SIZE],
+    head: usize,
+    len: usize,
+}
+
+#[cfg(feature = "uart-tx-fifo")]
+impl TxFifo {
+    const fn new() -> Self {
+        TxFifo {
+            buf: [0; TX_FIFO_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == TX_FIFO_SIZE {
+            return false;
+        }
+        self.buf[(self.head + self.len) % TX_FIFO_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn front(&self) -> Option<u8> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(self.buf[self.head])
+        }
+    }
+
+    fn pop(&mut self) {
+        self.head = (self.head + 1) % TX_FIFO_SIZE;
+        self.len -= 1;
+    }
+}
+
+/// 提供给rustsbi的传统控制台，和固件日志共用控制台的发送队列
+///
+/// 每次写入后不等待发送完成，SBI调用可以尽快返回。
+pub struct SbiConsole {
+    index: usize,
+}
+
+impl SbiConsole {
+    pub fn new(uart: Uart) -> Self {
+        register(uart);
+        SbiConsole {
+            index: uart.index(),
+        }
+    }
+}
+
+impl rustsbi::legacy_stdio::LegacyStdio for SbiConsole {
+    fn getchar(&mut self) -> u8 {
+        loop {
+            // 每次尝试后都释放锁，等待输入时不影响其它核输出
+            let mut lock = CONSOLES.lock();
+            let ans = lock[self.index].as_mut().map(|console| console.uart.read());
+            drop(lock);
+            match ans {
+                Some(Ok(byte)) => return byte,
+                Some(Err(nb::Error::WouldBlock)) => core::hint::spin_loop(),
+                Some(Err(nb::Error::Other(e))) => match e {},
+                None => return 0,
+            }
+        }
+    }
+
+    fn putchar(&mut self, ch: u8) {
+        let mut lock = CONSOLES.lock();
+        if let Some(console) = lock[self.index].as_mut() {
+            console.write_byte(ch);
+        }
+        drop(lock);
+    }
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use fmt::Write;
-    let lock = STDOUT.lock();
-    if let Some(mut stdout) = *lock {
+    let index = STDOUT.load(Ordering::Acquire);
+    let mut lock = CONSOLES.lock();
+    if let Some(Some(stdout)) = lock.get_mut(index) {
         stdout.write_fmt(args).unwrap();
     }
     drop(lock);
@@ -34,9 +223,12 @@ pub fn _print(args: fmt::Arguments) {
 #[doc(hidden)]
 pub fn _eprint(args: fmt::Arguments) {
     use fmt::Write;
-    let lock = STDOUT.lock();
-    if let Some(mut stdout) = *lock {
+    let index = STDOUT.load(Ordering::Acquire);
+    let mut lock = CONSOLES.lock();
+    if let Some(Some(stdout)) = lock.get_mut(index) {
         stdout.write_fmt(args).unwrap();
+        // 错误信息之后固件往往会停止运行，等待它完整输出
+        stdout.flush();
     }
     drop(lock);
 }
@@ -0,0 +1,215 @@
+// 探测核实现了哪些CSR
+//
+// 不同的核实现的CSR不同：PMP项数和粒度、mhpmcounter的个数、time和厂商自定义的CSR都可能不同。
+// 访问没有实现的CSR会产生非法指令异常，所以探测时临时把mtvec换成probe_trap：
+// 它跳过出错的指令，并把t1设为1，探测的代码由此得知访问失败，继续执行。
+//
+// 每个核在hart_local::init中探测一次，结果保存在HartLocal中，初始化之后不再修改。
+// 模拟指令时按S态地址空间读取指令也使用同样的恢复方法，见feature::supervisor_hart。
+use bit_field::BitField;
+use core::arch::{asm, naked_asm};
+use riscv::register::{
+    mcause, mepc, mstatus, mtval, mtvec,
+    mtvec::{Mtvec, TrapMode},
+};
+
+/// 探测得到的当前核的CSR
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HartCsrs {
+    /// 实现了的PMP项数
+    pub pmp_count: usize,
+    /// PMP的粒度，单位是字节；没有找到关闭的PMP项、无法探测时是0
+    pub pmp_granularity: usize,
+    /// 实现了的mhpmcounter，第i位对应mhpmcounter{i}
+    pub hpm_counters: u32,
+    pub scounteren: bool,
+    pub mcountinhibit: bool,
+    pub menvcfg: bool,
+    pub time: bool,
+    /// SiFive 7系列核的Feature Disable CSR（0x7C1）
+    pub feature_disable: bool,
+    /// SiFive 7系列核的分支预测模式CSR（0x7C0）
+    pub branch_prediction_mode: bool,
+}
+
+/// 探测当前核的CSR，可以在设置陷入入口之前调用
+pub fn probe() -> HartCsrs {
+    with_trap_recovery(|| unsafe {
+        let (pmp_count, pmp_granularity) = probe_pmp();
+        HartCsrs {
+            pmp_count,
+            pmp_granularity,
+            hpm_counters: probe_hpm_counters(),
+            scounteren: read_csr::<0x106>().is_some(),
+            mcountinhibit: read_csr::<0x320>().is_some(),
+            menvcfg: read_csr::<0x30A>().is_some(),
+            time: read_csr::<0xC01>().is_some(),
+            feature_disable: read_csr::<0x7C1>().is_some(),
+            branch_prediction_mode: read_csr::<0x7C0>().is_some(),
+        }
+    })
+}
+
+/// 在可以恢复的陷入处理函数下执行f
+///
+/// f中的异常由probe_trap处理：跳过出错的4字节指令，并把t1设为1，f中的汇编代码据此判断是否出错。
+/// 期间关闭M态中断；结束后恢复陷入入口，以及被异常改写的mepc、mcause和mtval，
+/// 处理陷入的代码可以在其中调用，之后仍能读到原来陷入的信息。
+pub fn with_trap_recovery<T>(f: impl FnOnce() -> T) -> T {
+    let mie = mstatus::read().mie();
+    unsafe { mstatus::clear_mie() };
+    let (epc, cause, tval) = (mepc::read(), mcause::read().bits(), mtval::read());
+    let prev: Mtvec = mtvec::read();
+    unsafe { mtvec::write(probe_trap as *const () as usize, TrapMode::Direct) };
+    let ans = f();
+    unsafe { mtvec::write(prev.address(), prev.trap_mode().unwrap_or(TrapMode::Direct)) };
+    mepc::write(epc);
+    unsafe { asm!("csrw mcause, {}", "csrw mtval, {}", in(reg) cause, in(reg) tval) };
+    if mie {
+        unsafe { mstatus::set_mie() };
+    }
+    ans
+}
+
+// 跳过出错的指令（CSR指令和lhu都是4个字节），t1 <- 1
+#[unsafe(naked)]
+#[link_section = ".text"]
+unsafe extern "C" fn probe_trap() -> ! {
+    naked_asm!(
+        ".p2align 2",
+        "csrr   t1, mepc
+        addi    t1, t1, 4
+        csrw    mepc, t1
+        li      t1, 1
+        mret",
+    )
+}
+
+/// 读取CSR，没有实现时返回None
+#[inline]
+unsafe fn read_csr<const CSR: u16>() -> Option<usize> {
+    let (value, trapped): (usize, usize);
+    asm!(
+        "csrr   {value}, {csr}",
+        csr = const CSR,
+        value = out(reg) value,
+        inout("t1") 0usize => trapped,
+    );
+    (trapped == 0).then_some(value)
+}
+
+/// 把value写入CSR，返回原来的值；没有实现时返回None
+#[inline]
+unsafe fn swap_csr<const CSR: u16>(value: usize) -> Option<usize> {
+    let (old, trapped): (usize, usize);
+    asm!(
+        "csrrw  {old}, {csr}, {value}",
+        csr = const CSR,
+        value = in(reg) value,
+        old = out(reg) old,
+        inout("t1") 0usize => trapped,
+    );
+    (trapped == 0).then_some(old)
+}
+
+// 写入全1后读回，再恢复原来的值；没有实现或者读回0（只读的0）时返回None
+unsafe fn probe_writable(swap: unsafe fn(usize) -> Option<usize>) -> Option<usize> {
+    let old = swap(usize::MAX)?;
+    let written = swap(old)?;
+    (written != 0).then_some(written)
+}
+
+macro_rules! swap_csrs {
+    ($($csr:literal),* $(,)?) => {
+        [$(swap_csr::<$csr>),*]
+    };
+}
+
+const PMPADDR: [unsafe fn(usize) -> Option<usize>; 64] = swap_csrs!(
+    0x3B0, 0x3B1, 0x3B2, 0x3B3, 0x3B4, 0x3B5, 0x3B6, 0x3B7, 0x3B8, 0x3B9, 0x3BA, 0x3BB, 0x3BC,
+    0x3BD, 0x3BE, 0x3BF, 0x3C0, 0x3C1, 0x3C2, 0x3C3, 0x3C4, 0x3C5, 0x3C6, 0x3C7, 0x3C8, 0x3C9,
+    0x3CA, 0x3CB, 0x3CC, 0x3CD, 0x3CE, 0x3CF, 0x3D0, 0x3D1, 0x3D2, 0x3D3, 0x3D4, 0x3D5, 0x3D6,
+    0x3D7, 0x3D8, 0x3D9, 0x3DA, 0x3DB, 0x3DC, 0x3DD, 0x3DE, 0x3DF, 0x3E0, 0x3E1, 0x3E2, 0x3E3,
+    0x3E4, 0x3E5, 0x3E6, 0x3E7, 0x3E8, 0x3E9, 0x3EA, 0x3EB, 0x3EC, 0x3ED, 0x3EE, 0x3EF,
+);
+
+// 64位下只有偶数编号的pmpcfg，每个包含8项的配置
+#[cfg(target_pointer_width = "64")]
+const PMPCFG: [unsafe fn() -> Option<usize>; 8] = [
+    read_csr::<0x3A0>,
+    read_csr::<0x3A2>,
+    read_csr::<0x3A4>,
+    read_csr::<0x3A6>,
+    read_csr::<0x3A8>,
+    read_csr::<0x3AA>,
+    read_csr::<0x3AC>,
+    read_csr::<0x3AE>,
+];
+
+#[cfg(target_pointer_width = "64")]
+unsafe fn pmpcfg(index: usize) -> Option<u8> {
+    let bits = PMPCFG[index / 8]()?;
+    Some(bits.get_bits((index % 8) * 8..(index % 8) * 8 + 8) as u8)
+}
+
+// PMP项从0开始连续实现；pmpaddr可写的位数给出粒度：A为OFF时，低G位读出为0，粒度是2^(G+2)字节。
+// M态不受没有锁定的PMP项限制，探测时临时改写pmpaddr不影响固件运行。
+// 这一项锁定，或者下一项是锁定的TOR项时，pmpaddr的写入被忽略，不能用写入后读回的值判断，也不能用来计算粒度
+#[cfg(target_pointer_width = "64")]
+unsafe fn probe_pmp() -> (usize, usize) {
+    let mut count = 0;
+    let mut granularity = 0;
+    for (index, swap) in PMPADDR.iter().enumerate() {
+        let cfg = match pmpcfg(index) {
+            Some(cfg) => cfg,
+            None => break,
+        };
+        // 不能写入的项，只要pmpaddr能访问就是实现了的项
+        if cfg.get_bit(7) || below_locked_tor(index) {
+            match swap(0) {
+                Some(_) => count = index + 1,
+                None => break,
+            }
+            continue;
+        }
+        let written = match probe_writable(*swap) {
+            Some(written) => written,
+            None => break,
+        };
+        count = index + 1;
+        // 打开的项读出的低位和匹配方式有关，不能用来计算粒度
+        if granularity == 0 && cfg.get_bits(3..5) == 0 {
+            granularity = 1 << (written.trailing_zeros() + 2);
+        }
+    }
+    (count, granularity)
+}
+
+// 下一项是锁定的TOR项时，它用这一项的pmpaddr作为下界，这一项的pmpaddr也被锁定
+#[cfg(target_pointer_width = "64")]
+unsafe fn below_locked_tor(index: usize) -> bool {
+    if index + 1 >= PMPADDR.len() {
+        return false;
+    }
+    match pmpcfg(index + 1) {
+        Some(cfg) => cfg.get_bit(7) && cfg.get_bits(3..5) == 1,
+        None => false,
+    }
+}
+
+const MHPMCOUNTER: [unsafe fn(usize) -> Option<usize>; 29] = swap_csrs!(
+    0xB03, 0xB04, 0xB05, 0xB06, 0xB07, 0xB08, 0xB09, 0xB0A, 0xB0B, 0xB0C, 0xB0D, 0xB0E, 0xB0F,
+    0xB10, 0xB11, 0xB12, 0xB13, 0xB14, 0xB15, 0xB16, 0xB17, 0xB18, 0xB19, 0xB1A, 0xB1B, 0xB1C,
+    0xB1D, 0xB1E, 0xB1F,
+);
+
+// 没有实现的mhpmcounter是只读的0，写入全1后能读出非0值的才是实现了的计数器
+unsafe fn probe_hpm_counters() -> u32 {
+    let mut ans = 0;
+    for (index, swap) in MHPMCOUNTER.iter().enumerate() {
+        if probe_writable(*swap).is_some() {
+            ans |= 1 << (index + 3);
+        }
+    }
+    ans
+}
@@ -1,13 +1,22 @@
-// use alloc::collections::BTreeMap;
+use core::ops::Range;
 use serde_derive::Deserialize;
 use serde_device_tree::{self, error::Result};
 
 #[derive(Debug, Deserialize)]
 struct Tree<'a> {
-    // #[serde(borrow)]
-    // aliases: BTreeMap<&'a str, &'a str>,
+    #[serde(borrow)]
+    aliases: Option<Aliases<'a>>,
     #[serde(borrow)]
     chosen: Option<Chosen<'a>>,
+    #[serde(borrow, rename = "memory@80000000")]
+    memory: Option<Memory<'a>>,
+    soc: Option<Soc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Aliases<'a> {
+    serial0: Option<&'a str>,
+    serial1: Option<&'a str>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -16,13 +25,105 @@ struct Chosen<'a> {
     stdout_path: Option<&'a str>,
 }
 
-pub unsafe fn parse_device_tree(dtb_pa: usize) -> Result<()> {
-    let tree: Tree = serde_device_tree::from_raw(dtb_pa as *const u8)?;
-    use crate::console::println;
-    if let Some(chosen) = tree.chosen {
-        if let Some(stdout_path) = chosen.stdout_path {
-            println!("[rustsbi] stdout path: {}", stdout_path);
+#[derive(Debug, Deserialize)]
+struct Memory<'a> {
+    reg: &'a [u8],
+}
+
+#[derive(Debug, Deserialize)]
+struct Soc {
+    #[serde(rename = "serial@10010000")]
+    serial0: Option<Serial>,
+    #[serde(rename = "serial@10011000")]
+    serial1: Option<Serial>,
+}
+
+/// 设备树中串口节点的配置
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub struct Serial {
+    /// 输入时钟频率；设备树没有给出时，从PRCI读取
+    pub clock_frequency: Option<u32>,
+    /// 波特率；设备树没有给出时，使用默认值
+    pub current_speed: Option<u32>,
+}
+
+/// stdout-path选中的串口
+#[derive(Debug, Clone, Copy)]
+pub struct Stdout {
+    /// UART编号，0或1
+    pub uart_index: usize,
+    pub clock_frequency: Option<u32>,
+    /// stdout-path中`:`后的波特率优先，其次是节点的current-speed属性
+    pub baud_rate: Option<u32>,
+}
+
+/// 从设备树中得到的、固件需要使用的信息
+#[derive(Debug)]
+pub struct DeviceTreeInfo<'a> {
+    pub stdout_path: Option<&'a str>,
+    pub stdout: Option<Stdout>,
+    /// 内存节点的第一段物理地址
+    pub memory: Option<Range<usize>>,
+}
+
+pub unsafe fn parse_device_tree<'a>(dtb_pa: usize) -> Result<DeviceTreeInfo<'a>> {
+    let tree: Tree<'a> = serde_device_tree::from_raw(dtb_pa as *const u8)?;
+    let stdout_path = tree.chosen.as_ref().and_then(|chosen| chosen.stdout_path);
+    let stdout = stdout_path.and_then(|path| select_stdout(&tree, path));
+    let memory = tree
+        .memory
+        .as_ref()
+        .and_then(|memory| parse_reg(memory.reg));
+    Ok(DeviceTreeInfo {
+        stdout_path,
+        stdout,
+        memory,
+    })
+}
+
+// 根节点的#address-cells和#size-cells都是2，reg的前16个字节是第一段的地址和大小，按大端序存放
+fn parse_reg(reg: &[u8]) -> Option<Range<usize>> {
+    let cell = |index: usize| -> Option<u64> {
+        let bytes = reg.get(index * 4..index * 4 + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?) as u64)
+    };
+    let start = cell(0)? << 32 | cell(1)?;
+    let size = cell(2)? << 32 | cell(3)?;
+    let end = start.checked_add(size)?;
+    Some(start as usize..end as usize)
+}
+
+// stdout-path可以是别名或完整路径，后面可以跟着`:115200n8`这样的选项
+fn select_stdout(tree: &Tree, stdout_path: &str) -> Option<Stdout> {
+    let (path, options) = match stdout_path.split_once(':') {
+        Some((path, options)) => (path, Some(options)),
+        None => (stdout_path, None),
+    };
+    let path = if path.starts_with('/') {
+        path
+    } else {
+        let aliases = tree.aliases.as_ref()?;
+        match path {
+            "serial0" => aliases.serial0?,
+            "serial1" => aliases.serial1?,
+            _ => return None,
         }
-    }
-    Ok(())
+    };
+    let (uart_index, serial) = match path {
+        "/soc/serial@10010000" => (0, tree.soc.as_ref().and_then(|soc| soc.serial0)),
+        "/soc/serial@10011000" => (1, tree.soc.as_ref().and_then(|soc| soc.serial1)),
+        _ => return None,
+    };
+    let option_baud_rate = options.and_then(|options| {
+        let digits = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        options[..digits].parse().ok()
+    });
+    Some(Stdout {
+        uart_index,
+        clock_frequency: serial.and_then(|serial| serial.clock_frequency),
+        baud_rate: option_baud_rate.or(serial.and_then(|serial| serial.current_speed)),
+    })
 }
@@ -1,16 +1,15 @@
+use crate::hart_local::STACK_TOP_OFFSET;
 use riscv::register::{
-    mcause, mscratch,
+    mcause,
     mstatus::Mstatus,
     mtval,
     mtvec::{self, TrapMode},
 };
 
+// mscratch已经由hart_local::init指向当前核的HartLocal
 #[inline]
-pub fn init(hart_id: usize) {
-    let stack_base = unsafe { &super::SBI_STACK } as *const _ as usize;
-    let stack = stack_base + (hart_id + 1) * super::PER_HART_STACK_SIZE;
-    mscratch::write(stack);
-    let mut addr = early_trap_fail as usize;
+pub fn init() {
+    let mut addr = early_trap_fail as *const () as usize;
     if addr & 0x2 != 0 {
         addr += 0x2; // 中断入口地址必须对齐到4个字节
     }
@@ -65,13 +64,12 @@ pub struct SupervisorContext {
     pub mepc: usize,      // 32
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 pub unsafe extern "C" fn early_trap_fail() -> ! {
-    core::arch::asm!( // sp:特权级栈,mscratch:特权级上下文
+    core::arch::naked_asm!( // sp:特权级栈,mscratch:HartLocal
         ".p2align 2",
-        "csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:特权级上下文
-        "addi   sp, sp, -33*8",
+        "csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:HartLocal中的上下文
         "sd     ra, 0*8(sp)
         sd      gp, 2*8(sp)
         sd      tp, 3*8(sp)
@@ -109,8 +107,9 @@ pub unsafe extern "C" fn early_trap_fail() -> ! {
         "csrr   t2, mscratch
         sd      t2, 1*8(sp)",
         "mv     a0, sp",
+        "ld     sp, {stack_top}(sp)", // 新sp:这个核的M态栈顶
         "j      {fail}",
+        stack_top = const STACK_TOP_OFFSET,
         fail = sym rust_fail,
-        options(noreturn)
     )
 }
@@ -0,0 +1,131 @@
+// 按核的型号在启动时应用的勘误和特性设置
+//
+// 每一项设置只作用于mvendorid、marchid符合条件的核，并且可以用编译特性打开或关闭：
+// 默认打开的项在Cargo.toml的default特性中，用--no-default-features关闭。
+// 每个核启动时依次检查WORKAROUNDS中的每一项，应用符合条件且打开的项，并输出应用了哪些项。
+// 型号符合、但csr_probe没有探测到需要的CSR时不应用这一项，避免访问不存在的CSR。
+//
+// SiFive 7系列核（FU740的S7和U74）的Feature Disable CSR（0x7C1）每一位关闭一项微架构特性：
+// 第3位关闭指令缓存的推测填充，第11位关闭指令缓存的下一行预取，
+// 分支预测模式CSR（0x7C0）的第0位为1时使用静态分支预测。
+// 目前的几项都只是微架构特性的开关，不对应FU740或U74公开的某一条勘误，对这种核的所有版本（mimpid）都一样；
+// 以后加入只在部分版本上存在的勘误时，再按mimpid限定。
+use crate::console::println;
+use crate::csr_probe::HartCsrs;
+use alloc::vec::Vec;
+use riscv::register::{marchid, mimpid, mvendorid};
+
+const MVENDORID_SIFIVE: usize = 0x489;
+const MARCHID_SIFIVE7: usize = 0x8000_0000_0000_0007;
+
+const FEATURE_DISABLE_SPECULATIVE_ICACHE_REFILL: usize = 1 << 3;
+const FEATURE_DISABLE_ICACHE_NEXT_LINE_PREFETCH: usize = 1 << 11;
+
+/// 核的型号
+#[derive(Clone, Copy, Debug)]
+pub struct CoreId {
+    pub mvendorid: usize,
+    pub marchid: usize,
+    pub mimpid: usize,
+}
+
+impl CoreId {
+    /// 当前核的型号，没有实现的寄存器读出为0
+    pub fn read() -> Self {
+        CoreId {
+            mvendorid: mvendorid::read().map(|r| r.bits()).unwrap_or(0),
+            marchid: marchid::read().map(|r| r.bits()).unwrap_or(0),
+            mimpid: mimpid::read().map(|r| r.bits()).unwrap_or(0),
+        }
+    }
+}
+
+struct Workaround {
+    name: &'static str,
+    /// 编译时是否打开
+    enabled: bool,
+    /// 是否作用于这种核
+    affects: fn(CoreId) -> bool,
+    /// 需要的CSR是否存在
+    available: fn(&HartCsrs) -> bool,
+    apply: unsafe fn(),
+}
+
+// 按顺序应用：先打开所有特性，再按需要关闭其中的几项
+const WORKAROUNDS: &[Workaround] = &[
+    Workaround {
+        name: "sifive7-enable-features",
+        enabled: cfg!(feature = "errata-sifive7-enable-features"),
+        affects: is_sifive7,
+        available: has_feature_disable,
+        apply: sifive7_enable_features,
+    },
+    Workaround {
+        name: "sifive7-no-speculative-refill",
+        enabled: cfg!(feature = "errata-sifive7-no-speculative-refill"),
+        affects: is_sifive7,
+        available: has_feature_disable,
+        apply: sifive7_no_speculative_refill,
+    },
+    Workaround {
+        name: "sifive7-static-branch-prediction",
+        enabled: cfg!(feature = "errata-sifive7-static-branch-prediction"),
+        affects: is_sifive7,
+        available: has_branch_prediction_mode,
+        apply: sifive7_static_branch_prediction,
+    },
+];
+
+/// 在当前核上应用符合条件且打开的设置，输出应用了的和编译时关闭了的项
+pub fn apply(hart_id: usize, csrs: &HartCsrs) {
+    let core = CoreId::read();
+    let mut applied = Vec::new();
+    let mut disabled = Vec::new();
+    let affected = WORKAROUNDS
+        .iter()
+        .filter(|w| (w.affects)(core) && (w.available)(csrs));
+    for workaround in affected {
+        if workaround.enabled {
+            unsafe { (workaround.apply)() };
+            applied.push(workaround.name);
+        } else {
+            disabled.push(workaround.name);
+        }
+    }
+    println!(
+        "[rustsbi] hart {} core {:#x}:{:#x}:{:#x}, errata applied: [{}], disabled by build: [{}]",
+        hart_id,
+        core.mvendorid,
+        core.marchid,
+        core.mimpid,
+        applied.join(", "),
+        disabled.join(", ")
+    );
+}
+
+fn is_sifive7(core: CoreId) -> bool {
+    core.mvendorid == MVENDORID_SIFIVE && core.marchid == MARCHID_SIFIVE7
+}
+
+fn has_feature_disable(csrs: &HartCsrs) -> bool {
+    csrs.feature_disable
+}
+
+fn has_branch_prediction_mode(csrs: &HartCsrs) -> bool {
+    csrs.branch_prediction_mode
+}
+
+// 上一级引导程序可能关闭了一些特性，全部重新打开
+unsafe fn sifive7_enable_features() {
+    core::arch::asm!("csrw 0x7C1, zero");
+}
+
+unsafe fn sifive7_no_speculative_refill() {
+    let bits =
+        FEATURE_DISABLE_SPECULATIVE_ICACHE_REFILL | FEATURE_DISABLE_ICACHE_NEXT_LINE_PREFETCH;
+    core::arch::asm!("csrs 0x7C1, {}", in(reg) bits);
+}
+
+unsafe fn sifive7_static_branch_prediction() {
+    core::arch::asm!("csrsi 0x7C0, 1");
+}
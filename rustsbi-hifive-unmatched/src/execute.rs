@@ -1,72 +1,134 @@
+use crate::console::println;
+use crate::external_interrupt;
 use crate::feature;
+use crate::hart_local::{self, HartLocal};
+use crate::platform;
 use crate::runtime::{MachineTrap, Runtime, SupervisorContext};
-use core::{
-    ops::{Generator, GeneratorState},
-    pin::Pin,
-};
+use crate::timer::{self, FirmwareTimer};
+use crate::vendor_extension;
+use riscv::register::mip;
 use riscv::register::scause::{Exception, Trap};
-use riscv::register::{mie, mip};
+use rustsbi::SbiRet;
+use trap_emulation::IllegalInstruction;
 
 pub fn execute_supervisor(supervisor_mepc: usize, hart_id: usize, opaque: usize) {
     let mut rt = Runtime::new_sbi_supervisor(supervisor_mepc, hart_id, opaque);
+    timer::init_hart(rt.local_mut());
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::init_hart(rt.local_mut());
+    rt.local_mut().set_hsm_state(hart_local::HSM_STARTED);
     loop {
-        match Pin::new(&mut rt).resume(()) {
-            GeneratorState::Yielded(MachineTrap::SbiCall()) => {
-                let ctx = rt.context_mut();
-                let param = [ctx.a0, ctx.a1, ctx.a2, ctx.a3, ctx.a4, ctx.a5];
-                let ans = rustsbi::ecall(ctx.a7, ctx.a6, param);
-                ctx.a0 = ans.error;
-                ctx.a1 = ans.value;
-                ctx.mepc = ctx.mepc.wrapping_add(4);
+        let trap = rt.run();
+        let local = rt.local_mut();
+        match trap {
+            MachineTrap::SbiCall() => {
+                local.stats.sbi_calls += 1;
+                if is_shutdown_call(local.context.a7) {
+                    println!(
+                        "[rustsbi] hart {} trap statistics: {:?}",
+                        local.hart_id, local.stats
+                    );
+                    // 关机前把控制台队列中的数据全部发出，避免最后的输出被截断
+                    crate::console::flush();
+                }
+                let hart = feature::SupervisorHart::new(local);
+                let (extension, function, param) = trap_emulation::sbi_call_args(&hart);
+                let ans = if is_set_timer_call(extension, function) {
+                    // S态的时刻和固件定时器共用mtimecmp，由timer模块设置
+                    timer::set_supervisor_deadline(local, timer_value(param));
+                    SbiRet::ok(0)
+                } else if let Some(ans) = vendor_extension::ecall(extension, function, param) {
+                    ans
+                } else {
+                    rustsbi::ecall(extension, function, param)
+                };
+                let mut hart = feature::SupervisorHart::new(local);
+                trap_emulation::complete_sbi_call(&mut hart, ans.error, ans.value);
             }
-            GeneratorState::Yielded(MachineTrap::IllegalInstruction()) => {
-                let ctx = rt.context_mut();
-                // FIXME: get_vaddr_u32这个过程可能出错。
-                let ins = unsafe { get_vaddr_u32(ctx.mepc) } as usize;
-                if !emulate_illegal_instruction(ctx, ins) {
-                    unsafe {
-                        if feature::should_transfer_trap(ctx) {
-                            feature::do_transfer_trap(
-                                ctx,
-                                Trap::Exception(Exception::IllegalInstruction),
-                            )
-                        } else {
-                            fail_illegal_instruction(ctx, ins)
-                        }
+            MachineTrap::IllegalInstruction() => {
+                local.stats.illegal_instructions += 1;
+                let mut hart = feature::SupervisorHart::new(local);
+                match trap_emulation::handle_illegal_instruction(&mut hart) {
+                    IllegalInstruction::Emulated => {}
+                    IllegalInstruction::Transfer => unsafe {
+                        feature::do_transfer_trap(
+                            &mut local.context,
+                            Trap::Exception(Exception::IllegalInstruction),
+                        )
+                    },
+                    IllegalInstruction::Fail(ins) => {
+                        fail_illegal_instruction(&mut local.context, ins as usize)
+                    }
+                    IllegalInstruction::FetchFailed(fault) => {
+                        panic!("cannot fetch instruction at {:#x}", fault.vaddr)
                     }
                 }
             }
-            GeneratorState::Yielded(MachineTrap::MachineTimer()) => unsafe {
-                mip::set_stimer();
-                mie::clear_mtimer();
-            },
-            GeneratorState::Yielded(MachineTrap::MachineSoft()) => todo!(),
-            GeneratorState::Complete(()) => break,
+            // 只转交给S态的M态时钟中断和软件中断由trap_vector直接处理，
+            // 固件定时器到期或者有其它核间中断工作时才会到这里
+            MachineTrap::MachineTimer() => {
+                local.stats.machine_timer += 1;
+                timer::handle_interrupt(local);
+            }
+            MachineTrap::MachineSoft() => {
+                local.stats.machine_soft += 1;
+                // 先清除msip再取出工作，之后登记的工作会伴随新的软件中断
+                local.clint.clear_soft(local.hart_id);
+                let work = local.take_ipi_pending();
+                if work & hart_local::IPI_SUPERVISOR_SOFT != 0 {
+                    unsafe { mip::set_ssoft() };
+                }
+            }
+            MachineTrap::MachineExternal() => {
+                local.stats.machine_external += 1;
+                external_interrupt::handle(local.hart_id)
+            }
         }
+        poll_console(local);
+    }
+}
+
+// mtime计数的10毫秒
+const CONSOLE_FLUSH_INTERVAL: u64 = platform::TIMEBASE_FREQUENCY / 100;
+
+// 推进控制台的软件发送队列；还有数据没有发出时设置固件定时器，
+// S态只经过快速路径陷入或者一直不陷入时，队列也能继续推进
+fn poll_console(local: &mut HartLocal) {
+    crate::console::poll();
+    if crate::console::pending() && !timer::is_scheduled(local, FirmwareTimer::ConsoleFlush) {
+        let deadline = local.clint.get_mtime() + CONSOLE_FLUSH_INTERVAL;
+        timer::schedule(local, FirmwareTimer::ConsoleFlush, deadline, poll_console);
     }
 }
 
+const EXTENSION_SRST: usize = 0x53525354;
+const LEGACY_SHUTDOWN: usize = 0x08;
+const EXTENSION_TIMER: usize = 0x54494D45;
+const LEGACY_SET_TIMER: usize = 0x00;
+
 #[inline]
-unsafe fn get_vaddr_u32(vaddr: usize) -> u32 {
-    let mut ans: u32;
-    core::arch::asm!("
-        li      {tmp}, (1 << 17)
-        csrrs   {tmp}, mstatus, {tmp}
-        lwu     {ans}, 0({vaddr})
-        csrw    mstatus, {tmp}
-        ",
-        tmp = out(reg) _,
-        vaddr = in(reg) vaddr,
-        ans = lateout(reg) ans
-    );
-    ans
+fn is_shutdown_call(extension: usize) -> bool {
+    extension == EXTENSION_SRST || extension == LEGACY_SHUTDOWN
 }
 
-fn emulate_illegal_instruction(ctx: &mut SupervisorContext, ins: usize) -> bool {
-    if feature::emulate_rdtime(ctx, ins) {
-        return true;
-    }
-    false
+// SBI_EXT_TIME的set_timer通常由fast_trap处理，这里处理传统扩展和快速路径没有接管的情况；
+// 两种set_timer都不经过rustsbi
+#[inline]
+fn is_set_timer_call(extension: usize, function: usize) -> bool {
+    (extension == EXTENSION_TIMER && function == 0) || extension == LEGACY_SET_TIMER
+}
+
+// set_timer的参数，RV32上由两个寄存器给出
+#[cfg(target_pointer_width = "64")]
+#[inline]
+fn timer_value(param: [usize; 6]) -> u64 {
+    param[0] as u64
+}
+
+#[cfg(target_pointer_width = "32")]
+#[inline]
+fn timer_value(param: [usize; 6]) -> u64 {
+    param[0] as u64 | (param[1] as u64) << 32
 }
 
 // 真·非法指令异常，是M层出现的
@@ -0,0 +1,73 @@
+// M态外部中断的分发
+//
+// 固件只处理自己登记过的中断源（比如串口接收、L2缓存ECC、温度传感器），
+// 这些中断源只在指定核的M态上下文中打开；其余中断源仍然由操作系统通过S态上下文处理。
+use crate::console::println;
+use crate::peripheral::Plic;
+use crate::platform::{self, PLIC_SOURCES};
+use crate::util::AmoMutex;
+use riscv::register::mie;
+
+/// 固件处理外部中断的函数，参数是中断源编号
+pub type Handler = fn(source: u32);
+
+#[derive(Clone, Copy)]
+struct Entry {
+    hart_id: usize,
+    handler: Handler,
+}
+
+static HANDLERS: AmoMutex<[Option<Entry>; PLIC_SOURCES as usize + 1]> =
+    AmoMutex::new([None; PLIC_SOURCES as usize + 1]);
+
+/// 登记由固件在第hart_id个核的M态处理的中断源
+///
+/// 对应的核调用`init_hart`时才会打开这个中断源。
+pub fn register(source: u32, hart_id: usize, priority: u32, handler: Handler) {
+    assert!(
+        (1..=PLIC_SOURCES).contains(&source),
+        "plic source should be in [1, {}]",
+        PLIC_SOURCES
+    );
+    let mut lock = HANDLERS.lock();
+    lock[source as usize] = Some(Entry { hart_id, handler });
+    drop(lock);
+    platform::plic().set_priority(source, priority);
+}
+
+/// 初始化当前核的M态上下文：先关闭上一级引导程序可能打开的中断源，再打开登记给这个核的中断源
+pub fn init_hart(hart_id: usize) {
+    let plic = platform::plic();
+    let context = Plic::machine_context(hart_id);
+    plic.disable_all(context);
+    let lock = HANDLERS.lock();
+    for (source, entry) in lock.iter().enumerate() {
+        if matches!(entry, Some(entry) if entry.hart_id == hart_id) {
+            plic.enable(context, source as u32);
+        }
+    }
+    drop(lock);
+    plic.set_threshold(context, 0);
+    unsafe { mie::set_mext() };
+}
+
+/// 处理M态外部中断，直到这个上下文没有待处理的中断
+pub fn handle(hart_id: usize) {
+    let plic = platform::plic();
+    let context = Plic::machine_context(hart_id);
+    while let Some(source) = plic.claim(context) {
+        let entry = HANDLERS.lock().get(source as usize).copied().flatten();
+        match entry {
+            Some(entry) => (entry.handler)(source),
+            None => {
+                // 没有登记的中断源不应该在M态上下文中打开，关闭它，防止反复进入中断
+                plic.disable(context, source);
+                println!(
+                    "[rustsbi] warning: unexpected external interrupt source {} on hart {}, disabled",
+                    source, hart_id
+                );
+            }
+        }
+        plic.complete(context, source);
+    }
+}
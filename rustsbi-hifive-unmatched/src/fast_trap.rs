@@ -0,0 +1,243 @@
+// 陷入的快速路径
+//
+// Linux频繁读取time（U74没有实现time CSR，每次都会陷入M态）和调用set_timer。
+// 这两种陷入在异常入口处用汇编直接处理，只保存t0到t2三个寄存器；
+// 其余陷入恢复这三个寄存器后跳转到from_supervisor_save，走保存全部寄存器的完整路径。
+//
+// mtvec使用向量模式：异常从trap_vector的第0项进入fast_trap_entry；M态时钟中断和软件中断
+// 各有一段很短的处理程序，只把中断转交给S态；固件定时器到期时和M态外部中断一样走完整路径。
+//
+// 快速路径的行为和完整路径一致：rdtime按计数器策略模拟（time在所有有S态的核上都由固件模拟），
+// set_timer记录S态的时刻、按它和最早的固件定时器写入mtimecmp、清除S态时钟中断，见timer模块。
+// mscratch指向当前核的HartLocal，汇编代码按固定偏移读取核编号等字段，不需要读取mhartid。
+// 快速路径不调用console::poll，软件发送队列由其它陷入或者控制台的固件定时器推进。
+use crate::hart_local::{
+    FIRMWARE_DEADLINE_OFFSET, HART_ID_OFFSET, IPI_PENDING_OFFSET, IPI_SUPERVISOR_SOFT,
+    SUPERVISOR_DEADLINE_OFFSET,
+};
+use crate::platform::CLINT_BASE;
+use crate::runtime::from_supervisor_save;
+use core::arch::naked_asm;
+
+const MTIMECMP_BASE: usize = CLINT_BASE + 0x4000;
+const MTIME: usize = CLINT_BASE + 0xbff8;
+
+const EXTENSION_TIMER: usize = 0x54494D45;
+
+/// 向量表的对齐，mtvec的基地址必须按这个值对齐
+pub const TRAP_VECTOR_ALIGN: usize = 256;
+
+#[unsafe(naked)]
+#[link_section = ".text"]
+pub unsafe extern "C" fn trap_vector() -> ! {
+    naked_asm!(
+        ".p2align 8",
+        // 每一项都是一条不压缩的跳转指令，第i项对应mcause为i的中断
+        ".option push
+        .option norvc",
+        "j      {fast_trap_entry}", // 0: 所有异常
+        "j      {from_supervisor_save}", // 1: S态软件中断，已经委托给S态
+        "j      {from_supervisor_save}",
+        "j      1f", // 3: M态软件中断
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}", // 5: S态时钟中断，已经委托给S态
+        "j      {from_supervisor_save}",
+        "j      2f", // 7: M态时钟中断
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}", // 9: S态外部中断，已经委托给S态
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}", // 11: M态外部中断
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}",
+        "j      {from_supervisor_save}",
+        ".option pop",
+        // M态软件中断：清除这个核的msip，再取出转交给S态的核间中断，有这项工作时设置mip.SSIP；
+        // 还有其它登记的工作时，留给完整路径处理
+    "1:  csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:HartLocal
+        "sd     t0, 4*8(sp)
+        sd      t1, 5*8(sp)",
+        "ld     t0, {hart_id}(sp)
+        slli    t0, t0, 2
+        li      t1, {clint_base}
+        add     t1, t1, t0
+        sw      zero, 0(t1)
+        fence   o, rw",
+        "li     t0, ~{ipi_supervisor_soft}
+        addi    t1, sp, {ipi_pending}
+        amoand.d.aqrl t0, t0, (t1)",
+        "andi   t1, t0, {ipi_supervisor_soft}
+        beqz    t1, 3f
+        li      t1, 1 << 1
+        csrs    mip, t1",
+    "3:  andi   t0, t0, ~{ipi_supervisor_soft}
+        bnez    t0, 5f",
+        "ld     t0, 4*8(sp)
+        ld      t1, 5*8(sp)
+        csrrw   sp, mscratch, sp
+        mret",
+        // M态时钟中断：固件定时器到期时交给完整路径处理；否则是S态的时刻到达，设置mip.STIP，
+        // 清除S态时刻，mtimecmp改为固件定时器的时刻（没有固件定时器时是u64::MAX）
+    "2:  csrrw  sp, mscratch, sp",
+        "sd     t0, 4*8(sp)
+        sd      t1, 5*8(sp)
+        sd      t2, 6*8(sp)",
+        "li     t0, {mtime}
+        ld      t0, 0(t0)
+        ld      t1, {firmware_deadline}(sp)
+        bgeu    t0, t1, 4f",
+        "li     t0, 1 << 5
+        csrs    mip, t0
+        li      t0, -1
+        sd      t0, {supervisor_deadline}(sp)",
+        "ld     t0, {hart_id}(sp)
+        slli    t0, t0, 3
+        li      t2, {mtimecmp}
+        add     t2, t2, t0
+        sd      t1, 0(t2)",
+        "ld     t0, 4*8(sp)
+        ld      t1, 5*8(sp)
+        ld      t2, 6*8(sp)
+        csrrw   sp, mscratch, sp
+        mret",
+    "4:  ld     t2, 6*8(sp)",
+    "5:  ld     t0, 4*8(sp)
+        ld      t1, 5*8(sp)
+        csrrw   sp, mscratch, sp
+        j       {from_supervisor_save}",
+        clint_base = const CLINT_BASE,
+        mtime = const MTIME,
+        mtimecmp = const MTIMECMP_BASE,
+        supervisor_deadline = const SUPERVISOR_DEADLINE_OFFSET,
+        firmware_deadline = const FIRMWARE_DEADLINE_OFFSET,
+        hart_id = const HART_ID_OFFSET,
+        ipi_pending = const IPI_PENDING_OFFSET,
+        ipi_supervisor_soft = const IPI_SUPERVISOR_SOFT,
+        fast_trap_entry = sym fast_trap_entry,
+        from_supervisor_save = sym from_supervisor_save,
+    )
+}
+
+#[unsafe(naked)]
+#[link_section = ".text"]
+pub unsafe extern "C" fn fast_trap_entry() -> ! {
+    naked_asm!( // sp:特权级栈,mscratch:特权级上下文
+        ".p2align 2",
+        "csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:特权级上下文
+        "sd     t0, 4*8(sp)
+        sd      t1, 5*8(sp)
+        sd      t2, 6*8(sp)",
+        "csrr   t0, mcause
+        li      t1, 2
+        beq     t0, t1, 1f
+        li      t1, 9
+        beq     t0, t1, 2f
+        j       9f",
+        // 非法指令异常：只处理mtval中给出指令编码的rdtime
+    "1:  csrr   t0, mtval
+        li      t1, 0xFFFFF07F
+        and     t1, t0, t1
+        li      t2, 0xC0102073
+        bne     t1, t2, 9f",
+        // S态可以直接读取；U态还要看scounteren.TM，不允许时由完整路径转交给S态
+        "csrr   t1, mstatus
+        srli    t1, t1, 11
+        andi    t1, t1, 3
+        li      t2, 1
+        beq     t1, t2, 3f
+        bnez    t1, 9f
+        csrr    t1, scounteren
+        andi    t1, t1, 2
+        beqz    t1, 9f",
+        // t1 <- mtime; t0 <- rd
+    "3:  li     t1, {mtime}
+        ld      t1, 0(t1)
+        srli    t0, t0, 7
+        andi    t0, t0, 31",
+        // 跳转表每项两条不压缩的指令，共8个字节
+        "la     t2, 4f
+        slli    t0, t0, 3
+        add     t2, t2, t0
+        jr      t2",
+        ".option push
+        .option norvc",
+    "4:  nop",                  "j 5f", // x0
+        "mv     ra, t1",        "j 5f",
+        "csrw   mscratch, t1",  "j 5f", // sp保存在mscratch中
+        "mv     gp, t1",        "j 5f",
+        "mv     tp, t1",        "j 5f",
+        "sd     t1, 4*8(sp)",   "j 5f", // t0到t2在返回前从上下文恢复
+        "sd     t1, 5*8(sp)",   "j 5f",
+        "sd     t1, 6*8(sp)",   "j 5f",
+        "mv     s0, t1",        "j 5f",
+        "mv     s1, t1",        "j 5f",
+        "mv     a0, t1",        "j 5f",
+        "mv     a1, t1",        "j 5f",
+        "mv     a2, t1",        "j 5f",
+        "mv     a3, t1",        "j 5f",
+        "mv     a4, t1",        "j 5f",
+        "mv     a5, t1",        "j 5f",
+        "mv     a6, t1",        "j 5f",
+        "mv     a7, t1",        "j 5f",
+        "mv     s2, t1",        "j 5f",
+        "mv     s3, t1",        "j 5f",
+        "mv     s4, t1",        "j 5f",
+        "mv     s5, t1",        "j 5f",
+        "mv     s6, t1",        "j 5f",
+        "mv     s7, t1",        "j 5f",
+        "mv     s8, t1",        "j 5f",
+        "mv     s9, t1",        "j 5f",
+        "mv     s10, t1",       "j 5f",
+        "mv     s11, t1",       "j 5f",
+        "mv     t3, t1",        "j 5f",
+        "mv     t4, t1",        "j 5f",
+        "mv     t5, t1",        "j 5f",
+        "mv     t6, t1",        "j 5f",
+        ".option pop",
+    "5:  csrr   t0, mepc
+        addi    t0, t0, 4
+        csrw    mepc, t0
+        j       8f",
+        // S态环境调用：只处理SBI_EXT_TIME的set_timer
+    "2:  li     t0, {extension_timer}
+        bne     a7, t0, 9f
+        bnez    a6, 9f",
+        // mtimecmp <- min(新的S态时刻, 最早的固件定时器)
+        "sd     a0, {supervisor_deadline}(sp)
+        ld      t1, {firmware_deadline}(sp)
+        bgeu    a0, t1, 6f
+        mv      t1, a0",
+    "6:  ld     t0, {hart_id}(sp)
+        slli    t0, t0, 3
+        li      t2, {mtimecmp}
+        add     t2, t2, t0
+        sd      t1, 0(t2)",
+        // 清除mip.STIP；mie.MTIE一直是打开的
+        "li     t0, 1 << 5
+        csrc    mip, t0",
+        "li     a0, 0
+        li      a1, 0
+        csrr    t0, mepc
+        addi    t0, t0, 4
+        csrw    mepc, t0",
+        // 恢复t0到t2，返回S态
+    "8:  ld     t0, 4*8(sp)
+        ld      t1, 5*8(sp)
+        ld      t2, 6*8(sp)
+        csrrw   sp, mscratch, sp
+        mret",
+        // 交给完整路径，恢复到刚进入陷入时的状态
+    "9:  ld     t0, 4*8(sp)
+        ld      t1, 5*8(sp)
+        ld      t2, 6*8(sp)
+        csrrw   sp, mscratch, sp
+        j       {from_supervisor_save}",
+        mtime = const MTIME,
+        mtimecmp = const MTIMECMP_BASE,
+        extension_timer = const EXTENSION_TIMER,
+        supervisor_deadline = const SUPERVISOR_DEADLINE_OFFSET,
+        firmware_deadline = const FIRMWARE_DEADLINE_OFFSET,
+        hart_id = const HART_ID_OFFSET,
+        from_supervisor_save = sym from_supervisor_save,
+    )
+}
@@ -0,0 +1,49 @@
+use crate::platform;
+
+/// 按平台给出的策略设置当前核的mcounteren
+pub fn init_hart(hart_id: usize) {
+    let mcounteren = platform::counter_policy(hart_id).mcounteren();
+    unsafe { core::arch::asm!("csrw mcounteren, {}", in(reg) mcounteren) };
+}
+
+/// 读取M层计数器：0是mcycle，2是minstret，3到31是mhpmcounter3到mhpmcounter31
+#[cfg(target_pointer_width = "64")]
+pub fn read_machine_counter(index: usize) -> u64 {
+    assert!(
+        index <= 31 && index != 1,
+        "machine counter index should be 0, 2 or in [3, 31]"
+    );
+    let ans: usize;
+    unsafe {
+        core::arch::asm!(
+        // tmp <- 1的地址；len <- csrr和j指令的长度和
+        "la     {tmp}, 1f
+        la      {len}, 2f
+        sub     {len}, {len}, {tmp}",
+        // tmp <- tmp + id * len(csrr + j)
+        "mul    {id}, {id}, {len}
+        add     {tmp}, {tmp}, {id}
+        jr      {tmp}",
+    "1:  csrr   {ans}, 0xB00", "j   1f",
+    // 0xB01没有对应的CSR，不会被读取；这里占位，保证每一项的长度相同
+    "2:  csrr   {ans}, 0xB00", "j   1f",
+        "csrr   {ans}, 0xB02", "j   1f", "csrr   {ans}, 0xB03", "j   1f",
+        "csrr   {ans}, 0xB04", "j   1f", "csrr   {ans}, 0xB05", "j   1f",
+        "csrr   {ans}, 0xB06", "j   1f", "csrr   {ans}, 0xB07", "j   1f",
+        "csrr   {ans}, 0xB08", "j   1f", "csrr   {ans}, 0xB09", "j   1f",
+        "csrr   {ans}, 0xB0A", "j   1f", "csrr   {ans}, 0xB0B", "j   1f",
+        "csrr   {ans}, 0xB0C", "j   1f", "csrr   {ans}, 0xB0D", "j   1f",
+        "csrr   {ans}, 0xB0E", "j   1f", "csrr   {ans}, 0xB0F", "j   1f",
+        "csrr   {ans}, 0xB10", "j   1f", "csrr   {ans}, 0xB11", "j   1f",
+        "csrr   {ans}, 0xB12", "j   1f", "csrr   {ans}, 0xB13", "j   1f",
+        "csrr   {ans}, 0xB14", "j   1f", "csrr   {ans}, 0xB15", "j   1f",
+        "csrr   {ans}, 0xB16", "j   1f", "csrr   {ans}, 0xB17", "j   1f",
+        "csrr   {ans}, 0xB18", "j   1f", "csrr   {ans}, 0xB19", "j   1f",
+        "csrr   {ans}, 0xB1A", "j   1f", "csrr   {ans}, 0xB1B", "j   1f",
+        "csrr   {ans}, 0xB1C", "j   1f", "csrr   {ans}, 0xB1D", "j   1f",
+        "csrr   {ans}, 0xB1E", "j   1f", "csrr   {ans}, 0xB1F", "j   1f",
+    "1:",
+        id = inout(reg) index => _, tmp = out(reg) _, len = out(reg) _, ans = out(reg) ans);
+    }
+    ans as u64
+}
@@ -0,0 +1,128 @@
+use riscv::register::misa;
+
+// 固件按riscv64imac编译，汇编器不接受浮点指令，这里直接写出指令编码：
+// fmv.x.d a0, fN和fmv.d.x fN, a0。跳转表每项两条不压缩的指令，共8个字节。
+
+/// 这个核是否有双精度浮点单元；S7核没有
+#[inline]
+pub fn has_fpu() -> bool {
+    misa::read().map_or(false, |isa| isa.has_extension('D'))
+}
+
+/// 读取浮点寄存器f[index]，调用前mstatus.FS不能为Off
+#[inline]
+pub unsafe fn read_f(index: usize) -> u64 {
+    assert!(index <= 31, "index should be valid float register");
+    let ans: usize;
+    core::arch::asm!(
+    ".option push
+    .option norvc",
+    // tmp <- 1的地址；len <- 每一项的长度
+    "la     {tmp}, 1f
+    la      {len}, 2f
+    sub     {len}, {len}, {tmp}",
+    // tmp <- tmp + id * len
+    "mul    {id}, {id}, {len}
+    add     {tmp}, {tmp}, {id}
+    jr      {tmp}",
+"1:  .word   0xe2000553", "j   3f", // f0
+"2:  .word   0xe2008553", "j   3f", // f1
+    ".word   0xe2010553", "j   3f", // f2
+    ".word   0xe2018553", "j   3f", // f3
+    ".word   0xe2020553", "j   3f", // f4
+    ".word   0xe2028553", "j   3f", // f5
+    ".word   0xe2030553", "j   3f", // f6
+    ".word   0xe2038553", "j   3f", // f7
+    ".word   0xe2040553", "j   3f", // f8
+    ".word   0xe2048553", "j   3f", // f9
+    ".word   0xe2050553", "j   3f", // f10
+    ".word   0xe2058553", "j   3f", // f11
+    ".word   0xe2060553", "j   3f", // f12
+    ".word   0xe2068553", "j   3f", // f13
+    ".word   0xe2070553", "j   3f", // f14
+    ".word   0xe2078553", "j   3f", // f15
+    ".word   0xe2080553", "j   3f", // f16
+    ".word   0xe2088553", "j   3f", // f17
+    ".word   0xe2090553", "j   3f", // f18
+    ".word   0xe2098553", "j   3f", // f19
+    ".word   0xe20a0553", "j   3f", // f20
+    ".word   0xe20a8553", "j   3f", // f21
+    ".word   0xe20b0553", "j   3f", // f22
+    ".word   0xe20b8553", "j   3f", // f23
+    ".word   0xe20c0553", "j   3f", // f24
+    ".word   0xe20c8553", "j   3f", // f25
+    ".word   0xe20d0553", "j   3f", // f26
+    ".word   0xe20d8553", "j   3f", // f27
+    ".word   0xe20e0553", "j   3f", // f28
+    ".word   0xe20e8553", "j   3f", // f29
+    ".word   0xe20f0553", "j   3f", // f30
+    ".word   0xe20f8553", "j   3f", // f31
+"3:",
+    ".option pop",
+    id = inout(reg) index => _, tmp = out(reg) _, len = out(reg) _, out("a0") ans);
+    ans as u64
+}
+
+/// 写入浮点寄存器f[index]，调用前mstatus.FS不能为Off
+#[inline]
+pub unsafe fn write_f(index: usize, value: u64) {
+    assert!(index <= 31, "index should be valid float register");
+    core::arch::asm!(
+    ".option push
+    .option norvc",
+    "la     {tmp}, 1f
+    la      {len}, 2f
+    sub     {len}, {len}, {tmp}",
+    "mul    {id}, {id}, {len}
+    add     {tmp}, {tmp}, {id}
+    jr      {tmp}",
+"1:  .word   0xf2050053", "j   3f", // f0
+"2:  .word   0xf20500d3", "j   3f", // f1
+    ".word   0xf2050153", "j   3f", // f2
+    ".word   0xf20501d3", "j   3f", // f3
+    ".word   0xf2050253", "j   3f", // f4
+    ".word   0xf20502d3", "j   3f", // f5
+    ".word   0xf2050353", "j   3f", // f6
+    ".word   0xf20503d3", "j   3f", // f7
+    ".word   0xf2050453", "j   3f", // f8
+    ".word   0xf20504d3", "j   3f", // f9
+    ".word   0xf2050553", "j   3f", // f10
+    ".word   0xf20505d3", "j   3f", // f11
+    ".word   0xf2050653", "j   3f", // f12
+    ".word   0xf20506d3", "j   3f", // f13
+    ".word   0xf2050753", "j   3f", // f14
+    ".word   0xf20507d3", "j   3f", // f15
+    ".word   0xf2050853", "j   3f", // f16
+    ".word   0xf20508d3", "j   3f", // f17
+    ".word   0xf2050953", "j   3f", // f18
+    ".word   0xf20509d3", "j   3f", // f19
+    ".word   0xf2050a53", "j   3f", // f20
+    ".word   0xf2050ad3", "j   3f", // f21
+    ".word   0xf2050b53", "j   3f", // f22
+    ".word   0xf2050bd3", "j   3f", // f23
+    ".word   0xf2050c53", "j   3f", // f24
+    ".word   0xf2050cd3", "j   3f", // f25
+    ".word   0xf2050d53", "j   3f", // f26
+    ".word   0xf2050dd3", "j   3f", // f27
+    ".word   0xf2050e53", "j   3f", // f28
+    ".word   0xf2050ed3", "j   3f", // f29
+    ".word   0xf2050f53", "j   3f", // f30
+    ".word   0xf2050fd3", "j   3f", // f31
+"3:",
+    ".option pop",
+    id = inout(reg) index => _, tmp = out(reg) _, len = out(reg) _, in("a0") value as usize);
+}
+
+/// 读取fcsr，调用前mstatus.FS不能为Off
+#[inline]
+pub unsafe fn read_fcsr() -> u32 {
+    let ans: usize;
+    core::arch::asm!("csrr {}, 0x003", out(reg) ans);
+    ans as u32
+}
+
+/// 写入fcsr，调用前mstatus.FS不能为Off
+#[inline]
+pub unsafe fn write_fcsr(value: u32) {
+    core::arch::asm!("csrw 0x003, {}", in(reg) value as usize);
+}
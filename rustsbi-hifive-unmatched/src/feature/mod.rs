@@ -1,5 +1,7 @@
-mod emulate_rdtime;
+pub mod counters;
+mod fp;
+mod supervisor_hart;
 mod transfer_trap;
 
-pub use emulate_rdtime::emulate_rdtime;
+pub use supervisor_hart::SupervisorHart;
 pub use transfer_trap::{do_transfer_trap, should_transfer_trap};
@@ -0,0 +1,155 @@
+use super::{counters, fp};
+use crate::csr_probe;
+use crate::hart_local::HartLocal;
+use crate::platform;
+use bit_field::BitField;
+use riscv::register::{
+    mstatus::{self, FS, MPP},
+    mtval,
+};
+use trap_emulation::{CounterPolicy, FetchFault, FpState, Hart, PrivilegeMode};
+
+/// 陷入M层之前的核，供trap-emulation中的模拟逻辑使用
+pub struct SupervisorHart<'a> {
+    local: &'a mut HartLocal,
+}
+
+impl<'a> SupervisorHart<'a> {
+    #[inline]
+    pub fn new(local: &'a mut HartLocal) -> Self {
+        SupervisorHart { local }
+    }
+}
+
+impl Hart for SupervisorHart<'_> {
+    #[inline]
+    fn x(&self, index: usize) -> usize {
+        assert!(index <= 31, "index should be valid register target");
+        if index == 0 {
+            return 0;
+        }
+        let registers = unsafe { &*(&self.local.context as *const _ as *const [usize; 31]) };
+        registers[index - 1]
+    }
+    #[inline]
+    fn set_x(&mut self, index: usize, value: usize) {
+        assert!(index <= 31, "index should be valid register target");
+        if index == 0 {
+            // x0, don't modify
+            return;
+        }
+        let registers = unsafe { &mut *(&mut self.local.context as *mut _ as *mut [usize; 31]) };
+        registers[index - 1] = value;
+    }
+    #[inline]
+    fn mepc(&self) -> usize {
+        self.local.context.mepc
+    }
+    #[inline]
+    fn set_mepc(&mut self, mepc: usize) {
+        self.local.context.mepc = mepc;
+    }
+    #[inline]
+    fn previous_mode(&self) -> PrivilegeMode {
+        match self.local.context.mstatus.mpp() {
+            MPP::User => PrivilegeMode::User,
+            MPP::Supervisor => PrivilegeMode::Supervisor,
+            MPP::Machine => PrivilegeMode::Machine,
+        }
+    }
+    #[inline]
+    fn mtval(&self) -> usize {
+        // 读取指令出错时会恢复mtval，它仍然是陷入时的值
+        mtval::read()
+    }
+    #[inline]
+    fn fetch_u16(&mut self, vaddr: usize) -> Result<u16, FetchFault> {
+        unsafe { get_vaddr_u16(vaddr) }.ok_or(FetchFault { vaddr })
+    }
+    #[inline]
+    fn mtime(&self) -> u64 {
+        self.local.clint.get_mtime()
+    }
+    #[inline]
+    fn machine_counter(&self, index: usize) -> u64 {
+        // 没有实现的mhpmcounter读出为0，不访问它
+        if index >= 3 && !self.local.csrs.hpm_counters.get_bit(index) {
+            return 0;
+        }
+        counters::read_machine_counter(index)
+    }
+    #[inline]
+    fn scounteren(&self) -> u32 {
+        let ans: usize;
+        unsafe { core::arch::asm!("csrr {}, scounteren", out(reg) ans) };
+        ans as u32
+    }
+    #[inline]
+    fn counter_policy(&self) -> CounterPolicy {
+        platform::counter_policy(self.local.hart_id)
+    }
+    #[inline]
+    fn fp_state(&self) -> FpState {
+        if !fp::has_fpu() {
+            return FpState::Off;
+        }
+        match self.local.context.mstatus.fs() {
+            FS::Off => FpState::Off,
+            FS::Initial => FpState::Initial,
+            FS::Clean => FpState::Clean,
+            FS::Dirty => FpState::Dirty,
+        }
+    }
+    #[inline]
+    fn set_fp_state(&mut self, state: FpState) {
+        if !fp::has_fpu() {
+            return;
+        }
+        let fs = match state {
+            FpState::Off => FS::Off,
+            FpState::Initial => FS::Initial,
+            FpState::Clean => FS::Clean,
+            FpState::Dirty => FS::Dirty,
+        };
+        // 陷入期间mstatus只在这里修改，改完后写回上下文，返回S层时生效
+        unsafe { mstatus::set_fs(fs) };
+        self.local.context.mstatus = mstatus::read();
+    }
+    #[inline]
+    fn f_unchecked(&self, index: usize) -> u64 {
+        unsafe { fp::read_f(index) }
+    }
+    #[inline]
+    fn set_f_unchecked(&mut self, index: usize, value: u64) {
+        unsafe { fp::write_f(index, value) }
+    }
+    #[inline]
+    fn fcsr_unchecked(&self) -> u32 {
+        unsafe { fp::read_fcsr() }
+    }
+    #[inline]
+    fn set_fcsr_unchecked(&mut self, value: u32) {
+        unsafe { fp::write_fcsr(value) }
+    }
+}
+
+// 打开mstatus.MPRV，用陷入前的地址空间读取；同时打开MXR，只能执行的页也能读出指令。
+// 页表或PMP不允许访问时，lhu的异常由csr_probe的恢复处理函数跳过，返回None
+#[inline]
+unsafe fn get_vaddr_u16(vaddr: usize) -> Option<u16> {
+    csr_probe::with_trap_recovery(|| {
+        let (ans, trapped): (usize, usize);
+        core::arch::asm!("
+            li      {tmp}, (1 << 17) | (1 << 19)
+            csrrs   {tmp}, mstatus, {tmp}
+            lhu     {ans}, 0({vaddr})
+            csrw    mstatus, {tmp}
+            ",
+            tmp = out(reg) _,
+            vaddr = in(reg) vaddr,
+            ans = lateout(reg) ans,
+            inout("t1") 0usize => trapped,
+        );
+        (trapped == 0).then_some(ans as u16)
+    })
+}
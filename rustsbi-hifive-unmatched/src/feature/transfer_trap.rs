@@ -1,3 +1,5 @@
+use super::SupervisorHart;
+use crate::hart_local::HartLocal;
 use crate::runtime::SupervisorContext;
 use riscv::register::{
     mstatus::{self, MPP, SPP},
@@ -5,8 +7,8 @@ use riscv::register::{
 };
 
 #[inline]
-pub unsafe fn should_transfer_trap(ctx: &mut SupervisorContext) -> bool {
-    ctx.mstatus.mpp() != MPP::Machine
+pub unsafe fn should_transfer_trap(local: &mut HartLocal) -> bool {
+    trap_emulation::should_transfer_trap(&SupervisorHart::new(local))
 }
 
 #[inline]
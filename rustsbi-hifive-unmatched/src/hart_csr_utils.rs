@@ -1,4 +1,5 @@
 use crate::console::println;
+use crate::csr_probe::HartCsrs;
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -8,17 +9,46 @@ use riscv::register::{
     misa::{self, MXL},
 };
 
-pub fn print_hart0_csrs() {
+pub fn print_hart0_csrs(csrs: &HartCsrs) {
     print_misa();
     // 第0个核没有S态，不能委托中断
-    print_pmp();
+    print_probed_csrs(csrs);
+    print_pmp(csrs);
 }
 
-pub fn print_hartn_csrs() {
+pub fn print_hartn_csrs(csrs: &HartCsrs) {
     print_misa();
     print_mideleg();
     print_medeleg();
-    print_pmp();
+    print_probed_csrs(csrs);
+    print_pmp(csrs);
+}
+
+fn print_probed_csrs(csrs: &HartCsrs) {
+    let optional = [
+        ("scounteren", csrs.scounteren),
+        ("mcountinhibit", csrs.mcountinhibit),
+        ("menvcfg", csrs.menvcfg),
+        ("time", csrs.time),
+        ("0x7c0", csrs.branch_prediction_mode),
+        ("0x7c1", csrs.feature_disable),
+    ];
+    let implemented: Vec<&str> = optional
+        .iter()
+        .filter(|(_, implemented)| *implemented)
+        .map(|(name, _)| *name)
+        .collect();
+    let hpm_counters: Vec<String> = (3..32)
+        .filter(|i| csrs.hpm_counters.get_bit(*i))
+        .map(|i| format!("{}", i))
+        .collect();
+    println!(
+        "[rustsbi] pmp entries: {}, granularity: {}, mhpmcounters: [{}], optional csrs: [{}]",
+        csrs.pmp_count,
+        csrs.pmp_granularity,
+        hpm_counters.join(", "),
+        implemented.join(", ")
+    );
 }
 
 #[inline]
@@ -125,8 +155,8 @@ fn print_medeleg() {
 
 #[cfg(target_pointer_width = "64")]
 #[inline]
-fn print_pmp() {
-    let pmps = unsafe { pmps::<16>() };
+fn print_pmp(csrs: &HartCsrs) {
+    let pmps = unsafe { pmps(csrs.pmp_count) };
     for (i, (pmpicfg, pmpiaddr)) in pmps.iter().enumerate() {
         let pmpicfg = PmpCfg::from(*pmpicfg);
         let range = match pmpicfg.a() {
@@ -203,23 +233,18 @@ enum AddressMatching {
     Napot,
 }
 
-// 1.12中，L=64；1.11中，L=16。
-// 0..16 => pmpcfg[0, 2]
-// 0..64 => pmpcfg[0, 2, 4, 6, .., 14]
+// 读取前count个PMP项，count由csr_probe探测得到，没有实现的项不会被访问
+// 64位下，第i项在pmpcfg[i / 8 * 2]中；32位下，第i项在pmpcfg[i / 4]中
 #[inline]
-unsafe fn pmps<const L: usize>() -> [(u8, usize); L] {
-    assert!(L < 64, "in pmpxcfg, x should be in [0, 64)");
+unsafe fn pmps(count: usize) -> Vec<(u8, usize)> {
+    assert!(count <= 64, "pmp entry count should be in [0, 64]");
     let xlen: usize = core::mem::size_of::<usize>() * 8;
     let cfgs_in_pmpcfg: usize = xlen / 8;
-    let pmpcfg_max_id: usize = L / cfgs_in_pmpcfg;
-    let mut ans = [(0, 0); L];
-    for i in (0..pmpcfg_max_id).step_by(xlen / 32) {
-        let pmpcfgi = pmpcfg_r(i).to_le_bytes();
-        for j in 0..cfgs_in_pmpcfg {
-            let pmpaddr_id = i * 4 + j;
-            let pmpaddri = pmpaddr_r(pmpaddr_id);
-            ans[pmpaddr_id] = (pmpcfgi[j], pmpaddri);
-        }
+    let mut ans = Vec::with_capacity(count);
+    for pmpaddr_id in 0..count {
+        let pmpcfg_id = pmpaddr_id / cfgs_in_pmpcfg * (xlen / 32);
+        let pmpcfgi = pmpcfg_r(pmpcfg_id).to_le_bytes();
+        ans.push((pmpcfgi[pmpaddr_id % cfgs_in_pmpcfg], pmpaddr_r(pmpaddr_id)));
     }
     ans
 }
@@ -0,0 +1,152 @@
+// 每个核的局部数据
+//
+// 每个核有一个HartLocal，放在按核编号索引的静态数组中。HartLocal的第一个字段是特权级上下文，
+// mscratch中保存的上下文指针同时也是当前核HartLocal的指针：陷入处理的汇编代码和Rust代码
+// 都能从mscratch直接找到当前核的数据，不需要读取mhartid，也不需要加锁。
+//
+// 初始化之后，mscratch在M态总是指向当前核的HartLocal（S态运行时也是，陷入时和sp交换）。
+// 只有hsm_state和ipi_pending会被其它核访问，它们是原子变量；其余字段只由所属的核读写。
+use crate::csr_probe::{self, HartCsrs};
+use crate::peripheral::Clint;
+use crate::platform::{self, HART_COUNT};
+use crate::runtime::SupervisorContext;
+use crate::timer::TimerQueue;
+use core::mem::{offset_of, size_of, MaybeUninit};
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::register::mscratch;
+
+/// 汇编代码访问的字段相对HartLocal起始位置的偏移
+pub const SUPERVISOR_DEADLINE_OFFSET: usize = offset_of!(HartLocal, supervisor_deadline);
+pub const FIRMWARE_DEADLINE_OFFSET: usize = offset_of!(HartLocal, firmware_deadline);
+pub const HART_ID_OFFSET: usize = offset_of!(HartLocal, hart_id);
+pub const STACK_TOP_OFFSET: usize = offset_of!(HartLocal, stack_top);
+pub const IPI_PENDING_OFFSET: usize = offset_of!(HartLocal, ipi_pending);
+
+// mscratch既是上下文指针也是HartLocal指针；汇编代码用ld、sd和amo指令按双字访问上面的字段
+const _: () = {
+    assert!(offset_of!(HartLocal, context) == 0);
+    assert!(SUPERVISOR_DEADLINE_OFFSET % 8 == 0 && FIRMWARE_DEADLINE_OFFSET % 8 == 0);
+    assert!(HART_ID_OFFSET % 8 == 0 && STACK_TOP_OFFSET % 8 == 0);
+    assert!(IPI_PENDING_OFFSET % 8 == 0 && size_of::<AtomicUsize>() == 8);
+};
+
+/// 核间中断要完成的工作：设置mip.SSIP，把核间中断转交给S态
+///
+/// 固件自己唤醒其它核时只写msip，不设置这一位，S态不会收到多余的软件中断。
+pub const IPI_SUPERVISOR_SOFT: usize = 1 << 0;
+
+// HSM扩展定义的核状态
+pub const HSM_STARTED: usize = 0;
+#[allow(unused)]
+pub const HSM_STOPPED: usize = 1;
+pub const HSM_START_PENDING: usize = 2;
+
+#[repr(C)]
+pub struct HartLocal {
+    /// 必须是第一个字段，陷入时汇编代码按mscratch访问它
+    pub context: SupervisorContext,
+    /// S态通过set_timer设置的时刻，没有设置或者已经到达时是u64::MAX；快速路径按固定偏移访问
+    pub supervisor_deadline: u64,
+    /// 最早的固件定时器到期的时刻，由timer模块根据timers更新；快速路径按固定偏移读取
+    pub firmware_deadline: u64,
+    pub hart_id: usize,
+    /// 这个核的M态栈，栈从stack_top向stack_bottom增长
+    pub stack_bottom: usize,
+    pub stack_top: usize,
+    ipi_pending: AtomicUsize,
+    hsm_state: AtomicUsize,
+    pub stats: TrapStats,
+    pub timers: TimerQueue,
+    pub clint: Clint,
+    /// 启动时探测到的CSR，初始化之后不再修改
+    pub csrs: HartCsrs,
+}
+
+/// 经过完整陷入路径的陷入次数；快速路径处理的陷入不计入
+#[derive(Debug, Default)]
+pub struct TrapStats {
+    pub sbi_calls: u64,
+    pub illegal_instructions: u64,
+    pub machine_timer: u64,
+    pub machine_soft: u64,
+    pub machine_external: u64,
+}
+
+// 在.bss段中，第0个核清零后其它核才会初始化自己的项
+static mut HART_LOCALS: MaybeUninit<[HartLocal; HART_COUNT]> = MaybeUninit::uninit();
+
+#[inline]
+fn local_ptr(hart_id: usize) -> *mut HartLocal {
+    assert!(hart_id < HART_COUNT, "hart id should be valid");
+    unsafe { (addr_of_mut!(HART_LOCALS) as *mut HartLocal).add(hart_id) }
+}
+
+/// 初始化当前核的HartLocal，让mscratch指向它
+///
+/// 必须在第0个核清零.bss段之后、设置陷入入口之前调用。
+pub fn init(hart_id: usize) {
+    let stack_base = unsafe { addr_of!(super::SBI_STACK) } as usize;
+    let ptr = local_ptr(hart_id);
+    let local = HartLocal {
+        context: unsafe { MaybeUninit::zeroed().assume_init() },
+        supervisor_deadline: u64::MAX,
+        firmware_deadline: u64::MAX,
+        hart_id,
+        stack_bottom: stack_base + hart_id * super::PER_HART_STACK_SIZE,
+        stack_top: stack_base + (hart_id + 1) * super::PER_HART_STACK_SIZE,
+        ipi_pending: AtomicUsize::new(0),
+        hsm_state: AtomicUsize::new(HSM_START_PENDING),
+        stats: TrapStats::default(),
+        timers: TimerQueue::new(),
+        clint: platform::clint(),
+        csrs: csr_probe::probe(),
+    };
+    unsafe { ptr.write(local) };
+    mscratch::write(ptr as usize);
+}
+
+/// 当前核的HartLocal
+///
+/// # Safety
+///
+/// 必须在`init`之后、M态处理陷入时调用，这时mscratch指向当前核的HartLocal。
+/// 返回的可变引用只能有一个，运行时持有它，其它代码通过运行时访问。
+#[inline]
+pub unsafe fn current() -> &'static mut HartLocal {
+    &mut *(mscratch::read() as *mut HartLocal)
+}
+
+/// 当前核启动时探测到的CSR
+///
+/// 必须在`init`之后调用。
+#[inline]
+pub fn csrs() -> HartCsrs {
+    unsafe { (*(mscratch::read() as *const HartLocal)).csrs }
+}
+
+impl HartLocal {
+    #[inline]
+    pub fn set_hsm_state(&self, state: usize) {
+        self.hsm_state.store(state, Ordering::Release);
+    }
+
+    /// 取出所有等待完成的核间中断工作
+    #[inline]
+    pub fn take_ipi_pending(&self) -> usize {
+        self.ipi_pending.swap(0, Ordering::AcqRel)
+    }
+}
+
+/// 第hart_id个核的HSM状态
+#[cfg_attr(not(feature = "watchdog"), allow(unused))]
+pub fn hsm_state(hart_id: usize) -> usize {
+    let state = unsafe { &*addr_of!((*local_ptr(hart_id)).hsm_state) };
+    state.load(Ordering::Acquire)
+}
+
+/// 给第hart_id个核登记核间中断工作；调用者随后写这个核的msip
+pub fn request_ipi(hart_id: usize, work: usize) {
+    let pending = unsafe { &*addr_of!((*local_ptr(hart_id)).ipi_pending) };
+    pending.fetch_or(work, Ordering::AcqRel);
+}
@@ -1,24 +1,32 @@
 #![no_std]
 #![no_main]
-#![feature(naked_functions, asm_const, asm_sym)]
-#![feature(generator_trait)]
-#![feature(default_alloc_error_handler)]
-#![feature(ptr_metadata)]
 
 extern crate alloc;
 
+mod cache;
 mod console;
+mod csr_probe;
 mod device_tree;
 mod early_trap;
+mod errata;
 mod execute;
+mod external_interrupt;
+mod fast_trap;
 mod feature;
 mod hart_csr_utils;
+mod hart_local;
 mod peripheral;
+mod platform;
 mod runtime;
+mod timer;
 mod util;
+mod vendor_extension;
+#[cfg(feature = "watchdog")]
+mod watchdog;
 
 use console::{eprintln, println};
 use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
 
 #[panic_handler]
 fn on_panic(info: &PanicInfo) -> ! {
@@ -27,15 +35,16 @@ fn on_panic(info: &PanicInfo) -> ! {
     loop {}
 }
 
-static DEVICE_TREE: &'static [u8] = include_bytes!("hifive-unmatched-a00.dtb");
-
 fn rust_main(hart_id: usize, opaque: usize) {
-    let clint = peripheral::Clint::new(0x2000000 as *mut u8);
+    let clint = platform::clint();
     if hart_id == 0 {
         init_bss();
-        let uart = unsafe { peripheral::Uart::preloaded_uart0() };
+        // 设备树还没有解析，先使用默认的UART和波特率输出固件日志
+        let uart = unsafe { peripheral::Uart::by_index(FIRMWARE_LOG_UART.unwrap_or(0)) }.unwrap();
+        uart.configure(platform::peripheral_clock_hz(), DEFAULT_BAUD_RATE)
+            .expect("default baud rate should be valid");
         crate::console::init_stdout(uart);
-        for target_hart_id in 0..=4 {
+        for target_hart_id in 0..=platform::MAX_HART_ID {
             if target_hart_id != 0 {
                 clint.send_soft(target_hart_id);
             }
@@ -43,16 +52,24 @@ fn rust_main(hart_id: usize, opaque: usize) {
     } else {
         pause(clint);
     }
-    let opaque = if opaque == 0 {
+    let opaque = match platform::DEVICE_TREE {
         // 如果上一级没有填写设备树文件，这一级填写
-        DEVICE_TREE.as_ptr() as usize
-    } else {
-        opaque
+        Some(device_tree) if opaque == 0 => device_tree.as_ptr() as usize,
+        _ => opaque,
     };
-    early_trap::init(hart_id);
+    hart_local::init(hart_id);
+    early_trap::init();
     if hart_id == 0 {
         init_heap(); // 必须先加载堆内存，才能使用rustsbi框架
-        let uart = unsafe { peripheral::Uart::preloaded_uart0() };
+        let info = match unsafe { device_tree::parse_device_tree(opaque) } {
+            Ok(info) => Some(info),
+            Err(e) => {
+                println!("[rustsbi] warning: choose from device tree error, {}", e);
+                None
+            }
+        };
+        let stdout = info.as_ref().and_then(|info| info.stdout);
+        let uart = init_console(stdout);
         init_rustsbi_stdio(uart);
         init_rustsbi_clint(clint);
         println!("[rustsbi] RustSBI version {}", rustsbi::VERSION);
@@ -61,15 +78,21 @@ fn rust_main(hart_id: usize, opaque: usize) {
             "[rustsbi] Implementation: RustSBI-HiFive-Unleashed Version {}",
             env!("CARGO_PKG_VERSION")
         );
-        if let Err(e) = unsafe { device_tree::parse_device_tree(opaque) } {
-            println!("[rustsbi] warning: choose from device tree error, {}", e);
+        println!("[rustsbi] Platform: {}", platform::PLATFORM_NAME);
+        if let Some(stdout_path) = info.as_ref().and_then(|info| info.stdout_path) {
+            println!("[rustsbi] stdout path: {}", stdout_path);
+        }
+        if let Some(index) = FIRMWARE_LOG_UART {
+            println!("[rustsbi] firmware log on uart{}", index);
         }
         println!(
-            "[rustsbi] enter supervisor 0x80200000, opaque register {:#x}",
+            "[rustsbi] enter supervisor {:#x}, opaque register {:#x}",
+            platform::SUPERVISOR_ENTRY,
             opaque
         );
-        hart_csr_utils::print_hart0_csrs();
-        for target_hart_id in 0..=4 {
+        hart_csr_utils::print_hart0_csrs(&hart_local::csrs());
+        cache::init(info.as_ref().and_then(|info| info.memory.clone()));
+        for target_hart_id in 0..=platform::MAX_HART_ID {
             if target_hart_id != 0 {
                 clint.send_soft(target_hart_id);
             }
@@ -78,12 +101,15 @@ fn rust_main(hart_id: usize, opaque: usize) {
         // 不是初始化核，先暂停
         delegate_interrupt_exception(); // 第0个核不能委托中断（@dram）
         if hart_id == 1 {
-            hart_csr_utils::print_hartn_csrs();
+            hart_csr_utils::print_hartn_csrs(&hart_local::csrs());
         }
         pause(clint);
     }
+    errata::apply(hart_id, &hart_local::csrs());
+    external_interrupt::init_hart(hart_id);
+    feature::counters::init_hart(hart_id);
     runtime::init();
-    execute::execute_supervisor(0x80200000, hart_id, opaque);
+    execute::execute_supervisor(platform::SUPERVISOR_ENTRY, hart_id, opaque);
 }
 
 fn init_bss() {
@@ -95,14 +121,54 @@ fn init_bss() {
         static sidata: u32;
     }
     unsafe {
-        r0::zero_bss(&mut sbss, &mut ebss);
-        r0::init_data(&mut sdata, &mut edata, &sidata);
+        r0::zero_bss(addr_of_mut!(sbss), addr_of_mut!(ebss));
+        r0::init_data(addr_of_mut!(sdata), addr_of_mut!(edata), &sidata);
+    }
+}
+
+const DEFAULT_BAUD_RATE: u32 = 115200;
+
+// 固件日志使用的UART编号；打开`log-uart1`特性时，固件日志固定输出到UART1，
+// 操作系统独占stdout-path选中的UART，否则固件日志也跟随stdout-path
+#[cfg(feature = "log-uart1")]
+const FIRMWARE_LOG_UART: Option<usize> = Some(1);
+#[cfg(not(feature = "log-uart1"))]
+const FIRMWARE_LOG_UART: Option<usize> = None;
+
+// 按stdout-path选中的串口设置SBI控制台，返回这个串口
+fn init_console(stdout: Option<device_tree::Stdout>) -> peripheral::Uart {
+    let index = stdout.map(|stdout| stdout.uart_index).unwrap_or(0);
+    let uart = unsafe { peripheral::Uart::by_index(index) }.unwrap();
+    let mut clock_hz = stdout
+        .and_then(|stdout| stdout.clock_frequency)
+        .unwrap_or_else(platform::peripheral_clock_hz);
+    let mut baud_rate = stdout
+        .and_then(|stdout| stdout.baud_rate)
+        .unwrap_or(DEFAULT_BAUD_RATE);
+    // 设备树中的值可能是0或者超出范围，这时使用平台的时钟频率和默认波特率
+    if uart.configure(clock_hz, baud_rate).is_err() {
+        println!(
+            "[rustsbi] warning: invalid uart clock {} Hz or baud rate {} from device tree, use baud rate {}",
+            clock_hz, baud_rate, DEFAULT_BAUD_RATE
+        );
+        clock_hz = platform::peripheral_clock_hz();
+        baud_rate = DEFAULT_BAUD_RATE;
+        uart.configure(clock_hz, baud_rate)
+            .expect("default baud rate should be valid");
+    }
+    if FIRMWARE_LOG_UART.is_none() {
+        crate::console::init_stdout(uart);
     }
+    println!(
+        "[rustsbi] console uart{}, clock {} Hz, baud rate {}",
+        index, clock_hz, baud_rate
+    );
+    uart
 }
 
 fn init_rustsbi_stdio(uart: peripheral::Uart) {
-    use rustsbi::legacy_stdio::init_legacy_stdio_embedded_hal;
-    init_legacy_stdio_embedded_hal(uart);
+    use rustsbi::legacy_stdio::init_legacy_stdio;
+    init_legacy_stdio(console::SbiConsole::new(uart));
 }
 
 fn init_rustsbi_clint(clint: peripheral::Clint) {
@@ -129,7 +195,7 @@ fn delegate_interrupt_exception() {
         medeleg::set_load_fault();
         medeleg::set_store_fault();
         mie::set_mext();
-        // 不打开mie::set_mtimer
+        // mie.MTIE由timer::init_hart打开
         mie::set_msoft();
     }
 }
@@ -170,20 +236,20 @@ fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
             .lock()
-            .init(HEAP_SPACE.as_ptr() as usize, SBI_HEAP_SIZE);
+            .init(addr_of!(HEAP_SPACE) as usize, SBI_HEAP_SIZE);
     }
 }
 
 const PER_HART_STACK_SIZE: usize = 4 * 4096; // 16KiB
-const SBI_STACK_SIZE: usize = 5 * PER_HART_STACK_SIZE; // 5 harts
+const SBI_STACK_SIZE: usize = platform::HART_COUNT * PER_HART_STACK_SIZE;
 #[link_section = ".bss.uninit"]
 static mut SBI_STACK: [u8; SBI_STACK_SIZE] = [0; SBI_STACK_SIZE];
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text.entry"]
 #[export_name = "_start"]
 unsafe extern "C" fn entry() -> ! {
-    core::arch::asm!(
+    core::arch::naked_asm!(
     // 1. clear all registers
     "li x1, 0
     li x2, 0
@@ -234,5 +300,5 @@ unsafe extern "C" fn entry() -> ! {
     per_hart_stack_size = const PER_HART_STACK_SIZE,
     stack = sym SBI_STACK,
     rust_main = sym rust_main,
-    options(noreturn))
+    )
 }
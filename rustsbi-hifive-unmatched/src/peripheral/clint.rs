@@ -37,12 +37,15 @@ impl Clint {
 
 impl rustsbi::Ipi for Clint {
     fn max_hart_id(&self) -> usize {
-        4
+        crate::platform::MAX_HART_ID
     }
 
     fn send_ipi_many(&self, hart_mask: rustsbi::HartMask) -> rustsbi::SbiRet {
         for i in 0..=self.max_hart_id() {
             if hart_mask.has_bit(i) {
+                // 先登记工作再写msip，对方清除msip后取出工作时一定能看到它
+                crate::hart_local::request_ipi(i, crate::hart_local::IPI_SUPERVISOR_SOFT);
+                unsafe { core::arch::asm!("fence w, o") };
                 self.send_soft(i);
             }
         }
@@ -50,9 +53,13 @@ impl rustsbi::Ipi for Clint {
     }
 }
 
+// 注册给rustsbi，使S态能探测到时钟扩展；S态的时刻和固件定时器共用mtimecmp，
+// set_timer调用通常由execute_supervisor直接交给timer模块处理。
+// rustsbi的分发方式改变、调用到这里时，同样交给timer模块设置当前核的S态时刻
 impl rustsbi::Timer for Clint {
     fn set_timer(&self, time_value: u64) {
-        let this_mhartid = riscv::register::mhartid::read();
-        self.set_timer(this_mhartid, time_value);
+        // rustsbi在当前核处理S态调用时调用这里，mscratch指向当前核的HartLocal
+        let local = unsafe { crate::hart_local::current() };
+        crate::timer::set_supervisor_deadline(local, time_value);
     }
 }
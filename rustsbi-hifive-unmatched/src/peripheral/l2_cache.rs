@@ -0,0 +1,115 @@
+use bit_field::BitField;
+
+const CONFIG: usize = 0x000;
+const WAY_ENABLE: usize = 0x008;
+// 每种ECC错误有一组寄存器：出错地址的低32位、高32位和计数。按FU740手册，计数寄存器是复位以来的累计次数，
+// 读取它只清除对应的中断，不清除计数
+const ECC_BASE: usize = 0x100;
+const ECC_STRIDE: usize = 0x20;
+const ECC_ADDRESS_LOW: usize = 0x0;
+const ECC_ADDRESS_HIGH: usize = 0x4;
+const ECC_COUNT: usize = 0x8;
+const FLUSH64: usize = 0x200;
+
+/// L2缓存报告的ECC错误种类，顺序和寄存器组的顺序相同
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EccError {
+    /// 目录中可以纠正的错误
+    DirectoryCorrected = 0,
+    /// 目录中不能纠正的错误
+    DirectoryUncorrected = 1,
+    /// 数据中可以纠正的错误
+    DataCorrected = 2,
+    /// 数据中不能纠正的错误
+    DataUncorrected = 3,
+}
+
+impl EccError {
+    pub const ALL: [EccError; 4] = [
+        EccError::DirectoryCorrected,
+        EccError::DirectoryUncorrected,
+        EccError::DataCorrected,
+        EccError::DataUncorrected,
+    ];
+}
+
+/// Config寄存器中的缓存结构
+#[derive(Clone, Copy, Debug)]
+pub struct L2Config {
+    pub banks: u32,
+    pub ways: u32,
+    pub sets: u32,
+    pub block_bytes: u32,
+}
+
+impl L2Config {
+    /// 缓存的总字节数，sets是每个bank的组数
+    pub fn capacity(&self) -> usize {
+        self.banks as usize * self.ways as usize * self.sets as usize * self.block_bytes as usize
+    }
+}
+
+// L2缓存控制器
+#[derive(Clone, Copy)]
+pub struct L2Cache {
+    base: *mut u8,
+}
+
+unsafe impl Send for L2Cache {}
+unsafe impl Sync for L2Cache {}
+
+impl L2Cache {
+    pub fn new(base: *mut u8) -> L2Cache {
+        L2Cache { base }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.base.add(offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(self.base.add(offset) as *mut u32, value) }
+    }
+
+    /// Config寄存器的值：banks、ways、lgSets、lgBlockBytes各占8位
+    pub fn config_raw(&self) -> u32 {
+        self.read(CONFIG)
+    }
+
+    pub fn config(&self) -> L2Config {
+        let config = self.config_raw();
+        L2Config {
+            banks: config.get_bits(0..8),
+            ways: config.get_bits(8..16),
+            sets: 1 << config.get_bits(16..24),
+            block_bytes: 1 << config.get_bits(24..32),
+        }
+    }
+
+    /// 已经打开的路数；复位后只打开一路
+    pub fn enabled_ways(&self) -> u32 {
+        self.read(WAY_ENABLE) + 1
+    }
+
+    /// 打开所有的路；WayEnable只能增加，不能关闭已经打开的路
+    pub fn enable_all_ways(&self) {
+        let ways = self.config().ways;
+        self.write(WAY_ENABLE, ways - 1);
+    }
+
+    /// 写回并作废包含这个物理地址的缓存块
+    pub fn flush64(&self, address: u64) {
+        unsafe { core::ptr::write_volatile(self.base.add(FLUSH64) as *mut u64, address) }
+    }
+
+    /// 读出一种ECC错误最近一次出错的地址和复位以来的累计次数，并清除它的中断
+    ///
+    /// 几次错误可能合并成一次中断，次数以计数寄存器为准。
+    pub fn take_ecc_error(&self, error: EccError) -> (u64, u32) {
+        let base = ECC_BASE + error as usize * ECC_STRIDE;
+        let low = self.read(base + ECC_ADDRESS_LOW) as u64;
+        let high = self.read(base + ECC_ADDRESS_HIGH) as u64;
+        let count = self.read(base + ECC_COUNT);
+        (high << 32 | low, count)
+    }
+}
@@ -3,3 +3,11 @@ pub(crate) mod uart;
 pub use uart::Uart;
 mod clint;
 pub use clint::Clint;
+#[cfg_attr(feature = "qemu-sifive-u", allow(unused))]
+mod prci;
+#[cfg_attr(feature = "qemu-sifive-u", allow(unused))]
+pub use prci::Prci;
+mod plic;
+pub use plic::Plic;
+mod l2_cache;
+pub use l2_cache::{EccError, L2Cache, L2Config};
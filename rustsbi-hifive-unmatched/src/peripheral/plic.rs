@@ -0,0 +1,87 @@
+const PRIORITY_BASE: usize = 0x0;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+// 平台级中断控制器，只操作各个核的M态上下文，S态上下文留给操作系统
+#[derive(Clone, Copy)]
+pub struct Plic {
+    base: *mut u8,
+    sources: u32,
+}
+
+unsafe impl Send for Plic {}
+unsafe impl Sync for Plic {}
+
+impl Plic {
+    /// sources是中断源的数量；中断源编号从1开始，0表示没有中断
+    pub fn new(base: *mut u8, sources: u32) -> Plic {
+        Plic { base, sources }
+    }
+
+    /// 第hart_id个核M态的上下文编号
+    ///
+    /// S7核（第0个核）只有M态上下文；每个U74核依次有M态和S态两个上下文。
+    pub fn machine_context(hart_id: usize) -> usize {
+        if hart_id == 0 {
+            0
+        } else {
+            hart_id * 2 - 1
+        }
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        unsafe { self.base.add(offset) as *mut u32 }
+    }
+
+    pub fn set_priority(&self, source: u32, priority: u32) {
+        let reg = self.reg(PRIORITY_BASE + source as usize * 4);
+        unsafe { core::ptr::write_volatile(reg, priority) }
+    }
+
+    fn enable_reg(&self, context: usize, source: u32) -> (*mut u32, u32) {
+        let offset = ENABLE_BASE + context * ENABLE_STRIDE + (source as usize / 32) * 4;
+        (self.reg(offset), 1 << (source % 32))
+    }
+
+    pub fn enable(&self, context: usize, source: u32) {
+        let (reg, mask) = self.enable_reg(context, source);
+        unsafe { core::ptr::write_volatile(reg, core::ptr::read_volatile(reg) | mask) }
+    }
+
+    pub fn disable(&self, context: usize, source: u32) {
+        let (reg, mask) = self.enable_reg(context, source);
+        unsafe { core::ptr::write_volatile(reg, core::ptr::read_volatile(reg) & !mask) }
+    }
+
+    /// 关闭这个上下文的全部中断源
+    pub fn disable_all(&self, context: usize) {
+        for word in 0..=(self.sources as usize / 32) {
+            let reg = self.reg(ENABLE_BASE + context * ENABLE_STRIDE + word * 4);
+            unsafe { core::ptr::write_volatile(reg, 0) }
+        }
+    }
+
+    pub fn set_threshold(&self, context: usize, threshold: u32) {
+        let reg = self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_THRESHOLD);
+        unsafe { core::ptr::write_volatile(reg, threshold) }
+    }
+
+    /// 领取一个中断，没有待处理的中断时返回None
+    pub fn claim(&self, context: usize) -> Option<u32> {
+        let reg = self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_CLAIM);
+        match unsafe { core::ptr::read_volatile(reg) } {
+            0 => None,
+            source => Some(source),
+        }
+    }
+
+    /// 通知中断处理完成，这个中断源才能再次产生中断
+    pub fn complete(&self, context: usize, source: u32) {
+        let reg = self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_CLAIM);
+        unsafe { core::ptr::write_volatile(reg, source) }
+    }
+}
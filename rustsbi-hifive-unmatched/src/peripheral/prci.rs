@@ -0,0 +1,51 @@
+use bit_field::BitField;
+
+/// HiFive Unmatched主板上hfclk输入的晶振频率
+pub const HFCLK_HZ: u32 = 26_000_000;
+
+const HFPCLK_PLLCFG: usize = 0x50;
+const HFPCLK_PLLSEL: usize = 0x58;
+const HFPCLK_DIV_REG: usize = 0x5C;
+
+// 电源、复位、时钟和中断（PRCI）模块，只读取时钟配置，不修改它
+#[derive(Clone, Copy)]
+pub struct Prci {
+    base: *mut u8,
+}
+
+unsafe impl Send for Prci {}
+unsafe impl Sync for Prci {}
+
+impl Prci {
+    pub fn new(base: *mut u8) -> Prci {
+        Prci { base }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.base.add(offset) as *const u32) }
+    }
+
+    /// 外设时钟pclk的频率，UART等外设使用这个时钟
+    pub fn pclk_hz(&self) -> u32 {
+        // hfpclkpllsel为1时，hfpclk直接使用hfclk，否则使用hfpclkpll的输出
+        let hfpclk = if self.read(HFPCLK_PLLSEL).get_bit(0) {
+            HFCLK_HZ as u64
+        } else {
+            pll_output_hz(HFCLK_HZ as u64, self.read(HFPCLK_PLLCFG))
+        };
+        // pclk = hfpclk / (div + 2)
+        let div = self.read(HFPCLK_DIV_REG) as u64 + 2;
+        (hfpclk / div) as u32
+    }
+}
+
+// 参考FU740手册第7.4节，PLL输出频率 = 参考频率 * 2 * (divf + 1) / ((divr + 1) * 2^divq)
+fn pll_output_hz(reference_hz: u64, pllcfg: u32) -> u64 {
+    if pllcfg.get_bit(24) {
+        return reference_hz; // bypass
+    }
+    let divr = pllcfg.get_bits(0..6) as u64;
+    let divf = pllcfg.get_bits(6..15) as u64;
+    let divq = pllcfg.get_bits(15..18);
+    reference_hz * 2 * (divf + 1) / ((divr + 1) << divq)
+}
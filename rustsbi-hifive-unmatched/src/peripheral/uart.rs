@@ -2,7 +2,7 @@ use core::convert::Infallible;
 use embedded_hal::serial::{Read, Write};
 use fu740_hal::pac;
 
-// UART that is initialized by prior steps of bootloading
+// UART外设；上一级引导程序可能已经初始化过它，也可以使用`configure`重新设置
 #[derive(Clone, Copy)]
 pub struct Uart {
     inner: *const pac::uart0::RegisterBlock,
@@ -14,10 +14,80 @@ unsafe impl Sync for Uart {}
 
 impl Uart {
     #[inline]
-    pub unsafe fn preloaded_uart0() -> Self {
+    pub unsafe fn uart0() -> Self {
         let inner = pac::UART0::ptr();
         Self { inner }
     }
+
+    #[inline]
+    pub unsafe fn uart1() -> Self {
+        let inner = pac::UART1::ptr();
+        Self { inner }
+    }
+
+    /// 按编号得到UART，FU740上只有UART0和UART1
+    #[inline]
+    pub unsafe fn by_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(Self::uart0()),
+            1 => Some(Self::uart1()),
+            _ => None,
+        }
+    }
+
+    /// UART的编号，0或1
+    #[inline]
+    pub fn index(&self) -> usize {
+        if self.inner == pac::UART1::ptr() {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// 根据外设时钟频率和波特率设置分频寄存器，并打开发送和接收
+    ///
+    /// 上一级引导程序可能没有初始化UART，此时不能依赖它留下的分频值。
+    /// 时钟频率和波特率来自设备树，得不到有效的分频值时返回错误，不改动UART。
+    pub fn configure(&self, clock_hz: u32, baud_rate: u32) -> Result<(), InvalidBaudRate> {
+        let div = divisor(clock_hz, baud_rate).ok_or(InvalidBaudRate)?;
+        let uart = unsafe { &*self.inner };
+        // 等待已经写入的数据发送完，避免更换分频值时输出乱码；
+        // 上一级留下的水标值未知，先把它设为1，txwm有效就表示发送队列为空
+        if uart.txctrl.read().txen().bit_is_set() {
+            unsafe { uart.txctrl.modify(|_, w| w.txcnt().bits(1)) };
+            while uart.ip.read().txwm().bit_is_clear() {
+                core::hint::spin_loop();
+            }
+        }
+        unsafe {
+            // 使用轮询方式，不产生中断
+            uart.ie
+                .write_with_zero(|w| w.txwm().clear_bit().rxwm().clear_bit());
+            uart.div.write_with_zero(|w| w.div().bits(div));
+            // 发送水标为1：发送队列为空时txwm有效；一个停止位
+            uart.txctrl
+                .write_with_zero(|w| w.txen().set_bit().nstop().clear_bit().txcnt().bits(1));
+            // 接收水标为0：接收队列中有数据时rxwm有效
+            uart.rxctrl
+                .write_with_zero(|w| w.rxen().set_bit().rxcnt().bits(0));
+        }
+        Ok(())
+    }
+}
+
+/// 时钟频率或波特率为0、波特率高于时钟频率，或者分频值超出16位分频寄存器的范围
+#[derive(Debug)]
+pub struct InvalidBaudRate;
+
+// 波特率 = 时钟频率 / (div + 1)，向上取整使实际波特率不超过目标值
+fn divisor(clock_hz: u32, baud_rate: u32) -> Option<u16> {
+    if clock_hz == 0 || baud_rate == 0 || baud_rate > clock_hz {
+        return None;
+    }
+    let (clock_hz, baud_rate) = (clock_hz as u64, baud_rate as u64);
+    let div = (clock_hz + baud_rate - 1) / baud_rate - 1;
+    u16::try_from(div).ok()
 }
 
 // Ref: fu740-hal
@@ -58,12 +128,18 @@ impl Write<u8> for Uart {
 
     #[inline]
     fn flush(&mut self) -> nb::Result<(), Infallible> {
-        Ok(()) // todo: 观察水标
-               // if unsafe { &*self.inner }.ip.read().txwm().bit_is_set() {
-               //     // FIFO count is below the receive watermark (1)
-               //     Ok(())
-               // } else {
-               //     Err(nb::Error::WouldBlock)
-               // }
+        let uart = unsafe { &*self.inner };
+        // configure把发送水标txcnt设为1，txwm有效说明发送队列已经空了；
+        // 最后一个字符可能还在移位寄存器中发送，不能马上关机或者复位
+        if uart.ip.read().txwm().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+        // 再等待大约一个字符的时间：起始位、8个数据位和1个停止位共10位，每位是div + 1个外设时钟周期。
+        // 每次读外设寄存器至少需要一个外设时钟周期，用读寄存器的次数计时
+        let bit_cycles = uart.div.read().div().bits() as u32 + 1;
+        for _ in 0..10 * bit_cycles {
+            uart.ip.read();
+        }
+        Ok(())
     }
 }
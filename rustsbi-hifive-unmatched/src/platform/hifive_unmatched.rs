@@ -0,0 +1,29 @@
+// SiFive HiFive Unmatched主板，FU740-C000处理器：一个S7核，四个U74核
+
+use crate::peripheral::Prci;
+
+pub const PLATFORM_NAME: &str = "SiFive HiFive Unmatched";
+
+pub const MAX_HART_ID: usize = 4;
+
+pub const CLINT_BASE: usize = 0x200_0000;
+pub const PLIC_BASE: usize = 0xc00_0000;
+pub const PRCI_BASE: usize = 0x1000_0000;
+pub const L2_CACHE_BASE: Option<usize> = Some(0x201_0000);
+
+/// L2缓存ECC错误的中断源，顺序和EccError相同
+pub const L2_CACHE_ECC_SOURCES: [u32; 4] = [19, 20, 21, 22];
+
+/// mtime每秒增加的值，即设备树中的timebase-frequency，mtime按1MHz的RTCCLK计时
+pub const TIMEBASE_FREQUENCY: u64 = 1_000_000;
+
+/// PLIC的中断源数量，即设备树中的riscv,ndev
+pub const PLIC_SOURCES: u32 = 69;
+
+/// 上一级引导程序没有提供设备树时使用的设备树
+pub const DEVICE_TREE: Option<&[u8]> = Some(include_bytes!("../hifive-unmatched-a00.dtb"));
+
+/// UART等外设使用的时钟频率，从PRCI的时钟配置中读出
+pub fn peripheral_clock_hz() -> u32 {
+    Prci::new(PRCI_BASE as *mut u8).pclk_hz()
+}
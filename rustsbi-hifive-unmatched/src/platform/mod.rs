@@ -0,0 +1,55 @@
+// 平台相关的常量和外设地址
+//
+// 默认编译HiFive Unmatched主板的固件；打开`qemu-sifive-u`特性时，编译在QEMU的sifive_u机器上运行的固件。
+// 两个平台的外设布局相近，这里只记录它们的差异。
+#[cfg(not(feature = "qemu-sifive-u"))]
+mod hifive_unmatched;
+#[cfg(not(feature = "qemu-sifive-u"))]
+pub use hifive_unmatched::*;
+
+#[cfg(feature = "qemu-sifive-u")]
+mod qemu_sifive_u;
+#[cfg(feature = "qemu-sifive-u")]
+pub use qemu_sifive_u::*;
+
+use crate::peripheral::{Clint, L2Cache, Plic};
+use trap_emulation::CounterPolicy;
+
+/// 核的数量，所有核都从0开始连续编号
+pub const HART_COUNT: usize = MAX_HART_ID + 1;
+
+/// 下一级引导程序（操作系统）的入口地址
+pub const SUPERVISOR_ENTRY: usize = 0x80200000;
+
+#[inline]
+pub fn clint() -> Clint {
+    Clint::new(CLINT_BASE as *mut u8)
+}
+
+#[inline]
+pub fn plic() -> Plic {
+    Plic::new(PLIC_BASE as *mut u8, PLIC_SOURCES)
+}
+
+#[inline]
+pub fn l2_cache() -> Option<L2Cache> {
+    L2_CACHE_BASE.map(|base| L2Cache::new(base as *mut u8))
+}
+
+/// 每个核的计数器开放策略
+///
+/// cycle和instret由硬件直接开放，S层读取时不陷入M层；U74没有实现time CSR，
+/// time和hpmcounter由固件模拟。第0个核没有S态，不开放任何计数器。
+pub fn counter_policy(hart_id: usize) -> CounterPolicy {
+    const DIRECT: u32 = CounterPolicy::CYCLE | CounterPolicy::INSTRET;
+    match hart_id {
+        0 => CounterPolicy {
+            direct: 0,
+            emulated: 0,
+        },
+        _ => CounterPolicy {
+            direct: DIRECT,
+            emulated: !DIRECT,
+        },
+    }
+}
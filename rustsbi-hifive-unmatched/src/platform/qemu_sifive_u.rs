@@ -0,0 +1,29 @@
+// QEMU的sifive_u机器，模拟FU540：一个E51核，最多四个U54核
+//
+// 使用`-smp 5`运行，使核的数量和HiFive Unmatched主板相同。
+
+pub const PLATFORM_NAME: &str = "QEMU sifive_u";
+
+pub const MAX_HART_ID: usize = 4;
+
+pub const CLINT_BASE: usize = 0x200_0000;
+pub const PLIC_BASE: usize = 0xc00_0000;
+
+/// QEMU没有模拟L2缓存控制器
+pub const L2_CACHE_BASE: Option<usize> = None;
+pub const L2_CACHE_ECC_SOURCES: [u32; 4] = [0; 4];
+
+/// mtime每秒增加的值，QEMU的CLINT使用默认的10MHz
+pub const TIMEBASE_FREQUENCY: u64 = 10_000_000;
+
+/// PLIC的中断源数量，QEMU按FU540设置
+pub const PLIC_SOURCES: u32 = 53;
+
+/// QEMU总是通过a1寄存器传入自己生成的设备树，不需要内置设备树
+pub const DEVICE_TREE: Option<&[u8]> = None;
+
+/// QEMU的PRCI按FU540的寄存器布局模拟，和FU740不同；QEMU的UART也不使用分频值，
+/// 这里直接给出sifive_u设备树中的外设时钟频率，只用于计算分频值
+pub fn peripheral_clock_hz() -> u32 {
+    500_000_000
+}
@@ -1,8 +1,5 @@
-use core::{
-    arch::asm,
-    ops::{Generator, GeneratorState},
-    pin::Pin,
-};
+use crate::hart_local::{self, HartLocal};
+use core::arch::naked_asm;
 use riscv::register::{
     mcause::{self, Exception, Interrupt, Trap},
     mstatus::{self, Mstatus, MPP},
@@ -12,61 +9,67 @@ use riscv::register::{
 
 #[inline]
 pub fn init() {
-    let mut addr = from_supervisor_save as usize;
-    if addr & 0x2 != 0 {
-        addr += 0x2; // 中断入口地址必须对齐到4个字节
-    }
-    unsafe { mtvec::write(addr, TrapMode::Direct) };
+    use crate::fast_trap::{trap_vector, TRAP_VECTOR_ALIGN};
+    // 向量表从函数内对齐后的位置开始
+    let addr =
+        (trap_vector as *const () as usize + TRAP_VECTOR_ALIGN - 1) & !(TRAP_VECTOR_ALIGN - 1);
+    unsafe { mtvec::write(addr, TrapMode::Vectored) };
 }
 
 pub struct Runtime {
-    context: SupervisorContext,
+    local: &'static mut HartLocal,
 }
 
 impl Runtime {
     pub fn new_sbi_supervisor(supervisor_mepc: usize, a0: usize, a1: usize) -> Self {
-        let context: SupervisorContext = unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
-        let mut ans = Runtime { context };
+        // 上下文是HartLocal的第一个字段，切换到S层后mscratch仍然指向当前核的HartLocal
+        let mut ans = Runtime {
+            local: unsafe { hart_local::current() },
+        };
         ans.prepare_supervisor(supervisor_mepc);
-        ans.context.a0 = a0;
-        ans.context.a1 = a1;
+        ans.local.context.a0 = a0;
+        ans.local.context.a1 = a1;
         ans
     }
 
     fn reset(&mut self) {
         unsafe { mstatus::set_mpp(MPP::Supervisor) };
-        self.context.mstatus = mstatus::read();
-        self.context.machine_stack = 0x2333333366666666; // 将会被resume函数覆盖
+        self.local.context.mstatus = mstatus::read();
+        self.local.context.machine_stack = 0x2333333366666666; // 将会被run函数覆盖
     }
 
     // 在处理异常的时候，使用context_mut得到运行时当前用户的上下文，可以改变上下文的内容
     pub fn context_mut(&mut self) -> &mut SupervisorContext {
-        &mut self.context
+        &mut self.local.context
+    }
+
+    // 当前核的局部数据，其中包含上下文
+    pub fn local_mut(&mut self) -> &mut HartLocal {
+        self.local
     }
 
     pub fn prepare_supervisor(&mut self, new_mepc: usize) {
         self.reset();
-        self.context.mepc = new_mepc;
+        self.local.context.mepc = new_mepc;
     }
-}
 
-impl Generator for Runtime {
-    type Yield = MachineTrap;
-    type Return = ();
-    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> GeneratorState<Self::Yield, Self::Return> {
-        unsafe { do_resume(&mut self.context as *mut _) };
+    /// 切换到S层运行，直到下一次陷入M层，返回陷入的原因
+    ///
+    /// 返回时上下文中保存了陷入前的寄存器，处理完陷入后再次调用这个函数即可回到S层继续运行。
+    pub fn run(&mut self) -> MachineTrap {
+        unsafe { do_resume(&mut self.local.context as *mut _) };
         let mtval = mtval::read();
-        let trap = match mcause::read().cause() {
+        match mcause::read().cause() {
             Trap::Exception(Exception::SupervisorEnvCall) => MachineTrap::SbiCall(),
             Trap::Exception(Exception::IllegalInstruction) => MachineTrap::IllegalInstruction(),
             Trap::Interrupt(Interrupt::MachineTimer) => MachineTrap::MachineTimer(),
             Trap::Interrupt(Interrupt::MachineSoft) => MachineTrap::MachineSoft(),
+            Trap::Interrupt(Interrupt::MachineExternal) => MachineTrap::MachineExternal(),
             e => panic!(
                 "unhandled exception: {:?}! mtval: {:x?}, ctx: {:x?}",
-                e, mtval, self.context
+                e, mtval, self.local.context
             ),
-        };
-        GeneratorState::Yielded(trap)
+        }
     }
 }
 
@@ -76,6 +79,7 @@ pub enum MachineTrap {
     IllegalInstruction(),
     MachineTimer(),
     MachineSoft(),
+    MachineExternal(),
 }
 
 #[derive(Debug)]
@@ -117,16 +121,16 @@ pub struct SupervisorContext {
     pub machine_stack: usize, // 33
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 unsafe extern "C" fn do_resume(_supervisor_context: *mut SupervisorContext) {
-    asm!("j     {from_machine_save}", from_machine_save = sym from_machine_save, options(noreturn))
+    naked_asm!("j     {from_machine_save}", from_machine_save = sym from_machine_save)
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 unsafe extern "C" fn from_machine_save(_supervisor_context: *mut SupervisorContext) -> ! {
-    asm!( // sp:机器栈顶
+    naked_asm!( // sp:机器栈顶
         "addi   sp, sp, -15*8", // sp:机器栈顶
         // 进入函数之前，已经保存了调用者寄存器，应当保存被调用者寄存器
         "sd     ra, 0*8(sp)
@@ -147,14 +151,13 @@ unsafe extern "C" fn from_machine_save(_supervisor_context: *mut SupervisorConte
         // a0:特权级上下文
         "j      {to_supervisor_restore}",
         to_supervisor_restore = sym to_supervisor_restore,
-        options(noreturn)
     )
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 pub unsafe extern "C" fn to_supervisor_restore(_supervisor_context: *mut SupervisorContext) -> ! {
-    asm!(
+    naked_asm!(
         // a0:特权级上下文
         "sd     sp, 33*8(a0)", // 机器栈顶放进特权级上下文
         "csrw   mscratch, a0", // 新mscratch:特权级上下文
@@ -197,16 +200,15 @@ pub unsafe extern "C" fn to_supervisor_restore(_supervisor_context: *mut Supervi
         "ld     sp, 1*8(sp)", // 新sp:特权级栈
         // sp:特权级栈, mscratch:特权级上下文
         "mret",
-        options(noreturn)
     )
 }
 
 // 中断开始
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 pub unsafe extern "C" fn from_supervisor_save() -> ! {
-    asm!( // sp:特权级栈,mscratch:特权级上下文
+    naked_asm!( // sp:特权级栈,mscratch:特权级上下文
         ".p2align 2",
         "csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:特权级上下文
         "sd     ra, 0*8(sp)
@@ -248,14 +250,13 @@ pub unsafe extern "C" fn from_supervisor_save() -> ! {
         "sd     t2, 1*8(sp)", // 保存特权级栈
         "j      {to_machine_restore}",
         to_machine_restore = sym to_machine_restore,
-        options(noreturn)
     )
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 unsafe extern "C" fn to_machine_restore() -> ! {
-    asm!(
+    naked_asm!(
         // mscratch:特权级上下文
         "csrr   sp, mscratch", // sp:特权级上下文
         "ld     sp, 33*8(sp)", // sp:机器栈
@@ -276,6 +277,5 @@ unsafe extern "C" fn to_machine_restore() -> ! {
         ld      s11, 14*8(sp)",
         "addi   sp, sp, 15*8", // sp:机器栈顶
         "jr     ra",           // 其实就是ret
-        options(noreturn)
     )
 }
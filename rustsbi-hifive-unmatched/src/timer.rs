@@ -0,0 +1,114 @@
+// M态时钟的复用
+//
+// 每个核只有一个mtimecmp，S态通过set_timer设置的时刻和固件自己的定时器（比如推进控制台发送队列）共用它。
+// 每个核的HartLocal中有一个定时器队列，mtimecmp总是设置为S态时刻和最早的固件定时器中较早的一个；
+// M态时钟中断到来时，先调用到期的固件定时器，只有S态的时刻已经到达时才设置mip.STIP。
+//
+// 初始化后mie.MTIE一直打开，没有待处理的时刻时mtimecmp为u64::MAX，不会产生中断。
+// trap_vector和fast_trap_entry按同样的规则处理S态时刻，固件定时器到期时交给这里处理。
+use crate::hart_local::HartLocal;
+use riscv::register::{mie, mip};
+
+/// 固件定时器的种类，每种最多有一个等待到期的事件
+#[derive(Clone, Copy, Debug)]
+pub enum FirmwareTimer {
+    /// 控制台的软件发送队列还有数据时，定时推进它
+    ConsoleFlush = 0,
+    /// 定时记录这个核的心跳，见watchdog模块
+    #[cfg(feature = "watchdog")]
+    Heartbeat = 1,
+}
+
+const FIRMWARE_TIMER_COUNT: usize = if cfg!(feature = "watchdog") { 2 } else { 1 };
+
+/// 固件定时器到期时调用的函数，可以在其中再次设置定时器
+pub type Callback = fn(local: &mut HartLocal);
+
+#[derive(Clone, Copy)]
+struct Event {
+    deadline: u64,
+    callback: Callback,
+}
+
+pub struct TimerQueue {
+    events: [Option<Event>; FIRMWARE_TIMER_COUNT],
+}
+
+impl TimerQueue {
+    pub const fn new() -> Self {
+        TimerQueue {
+            events: [None; FIRMWARE_TIMER_COUNT],
+        }
+    }
+
+    fn earliest(&self) -> u64 {
+        let deadlines = self.events.iter().flatten().map(|event| event.deadline);
+        deadlines.min().unwrap_or(u64::MAX)
+    }
+
+    // 取出一个已经到期的事件
+    fn take_expired(&mut self, now: u64) -> Option<Event> {
+        let slot = self
+            .events
+            .iter_mut()
+            .find(|event| matches!(event, Some(event) if event.deadline <= now))?;
+        slot.take()
+    }
+}
+
+/// 初始化当前核的M态时钟：没有待处理的时刻，打开M态时钟中断
+pub fn init_hart(local: &mut HartLocal) {
+    local.supervisor_deadline = u64::MAX;
+    reprogram(local);
+    unsafe { mie::set_mtimer() };
+}
+
+/// 处理S态的set_timer调用
+pub fn set_supervisor_deadline(local: &mut HartLocal, deadline: u64) {
+    local.supervisor_deadline = deadline;
+    // 新的时刻生效前，清除上一次的S态时钟中断；到时后由M态时钟中断再次设置
+    unsafe { mip::clear_stimer() };
+    reprogram(local);
+}
+
+/// 设置固件定时器，替换这种定时器之前设置的事件
+pub fn schedule(local: &mut HartLocal, timer: FirmwareTimer, deadline: u64, callback: Callback) {
+    local.timers.events[timer as usize] = Some(Event { deadline, callback });
+    reprogram(local);
+}
+
+/// 取消固件定时器
+#[allow(unused)]
+pub fn cancel(local: &mut HartLocal, timer: FirmwareTimer) {
+    local.timers.events[timer as usize] = None;
+    reprogram(local);
+}
+
+/// 这种固件定时器是否正在等待到期
+pub fn is_scheduled(local: &HartLocal, timer: FirmwareTimer) -> bool {
+    local.timers.events[timer as usize].is_some()
+}
+
+/// 处理M态时钟中断：调用到期的固件定时器，S态的时刻到达时设置mip.STIP
+pub fn handle_interrupt(local: &mut HartLocal) {
+    loop {
+        let now = local.clint.get_mtime();
+        if let Some(event) = local.timers.take_expired(now) {
+            (event.callback)(local);
+            continue;
+        }
+        if local.supervisor_deadline <= now {
+            local.supervisor_deadline = u64::MAX;
+            unsafe { mip::set_stimer() };
+        }
+        break;
+    }
+    reprogram(local);
+}
+
+// 按S态时刻和最早的固件定时器设置mtimecmp
+fn reprogram(local: &mut HartLocal) {
+    local.firmware_deadline = local.timers.earliest();
+    let next = local.supervisor_deadline.min(local.firmware_deadline);
+    local.clint.set_timer(local.hart_id, next);
+}
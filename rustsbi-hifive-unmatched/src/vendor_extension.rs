@@ -0,0 +1,86 @@
+// SiFive厂商SBI扩展
+//
+// 扩展号是0x09000000加上SiFive的mvendorid（0x489）。这个扩展由固件直接处理，不经过rustsbi；
+// 只有平台上有L2缓存控制器时，probe_extension才报告这个扩展存在。
+//
+// | 功能号 | 功能 | 参数 | 返回值 |
+// |---|---|---|---|
+// | 0 | L2缓存结构 | 无 | Config寄存器的值：banks、ways、lgSets、lgBlockBytes各占8位 |
+// | 1 | ECC错误次数 | a0：错误种类 | 这种错误发生的次数 |
+// | 2 | ECC错误地址 | a0：错误种类 | 这种错误最近一次出错的物理地址 |
+// | 3 | 写回并作废缓存 | a0：物理地址，a1：长度 | 0 |
+//
+// 错误种类：0是目录可纠正错误，1是目录不可纠正错误，2是数据可纠正错误，3是数据不可纠正错误。
+// 写回并作废缓存时，地址范围不全在设备树给出的内存中返回SBI_ERR_INVALID_ADDRESS；
+// 长度超过L2缓存的容量时返回SBI_ERR_INVALID_PARAM，更大的范围需要分多次写回。
+use crate::cache::{self, FlushError};
+use crate::peripheral::EccError;
+use crate::platform;
+use rustsbi::SbiRet;
+
+pub const EXTENSION_SIFIVE: usize = 0x0900_0489;
+
+const EXTENSION_BASE: usize = 0x10;
+const FUNCTION_BASE_PROBE_EXTENSION: usize = 0x3;
+
+const FUNCTION_L2_CONFIG: usize = 0;
+const FUNCTION_L2_ECC_COUNT: usize = 1;
+const FUNCTION_L2_ECC_LAST_ADDRESS: usize = 2;
+const FUNCTION_L2_FLUSH_RANGE: usize = 3;
+
+const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
+const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
+const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
+
+/// 处理厂商扩展的调用和对它的探测；不是这两种调用时返回None，交给rustsbi处理
+pub fn ecall(extension: usize, function: usize, param: [usize; 6]) -> Option<SbiRet> {
+    match extension {
+        EXTENSION_SIFIVE => Some(handle(function, param)),
+        EXTENSION_BASE
+            if function == FUNCTION_BASE_PROBE_EXTENSION && param[0] == EXTENSION_SIFIVE =>
+        {
+            Some(SbiRet::ok(is_available() as usize))
+        }
+        _ => None,
+    }
+}
+
+#[inline]
+fn is_available() -> bool {
+    platform::L2_CACHE_BASE.is_some()
+}
+
+fn handle(function: usize, param: [usize; 6]) -> SbiRet {
+    let l2_cache = match platform::l2_cache() {
+        Some(l2_cache) => l2_cache,
+        None => return error(SBI_ERR_NOT_SUPPORTED),
+    };
+    match function {
+        FUNCTION_L2_CONFIG => SbiRet::ok(l2_cache.config_raw() as usize),
+        FUNCTION_L2_ECC_COUNT => match ecc_error(param[0]) {
+            Some(kind) => SbiRet::ok(cache::ecc_count(kind) as usize),
+            None => error(SBI_ERR_INVALID_PARAM),
+        },
+        FUNCTION_L2_ECC_LAST_ADDRESS => match ecc_error(param[0]) {
+            Some(kind) => SbiRet::ok(cache::ecc_last_address(kind) as usize),
+            None => error(SBI_ERR_INVALID_PARAM),
+        },
+        FUNCTION_L2_FLUSH_RANGE => match cache::flush_range(param[0], param[1]) {
+            Ok(()) => SbiRet::ok(0),
+            Err(FlushError::NotSupported) => error(SBI_ERR_NOT_SUPPORTED),
+            Err(FlushError::InvalidAddress) => error(SBI_ERR_INVALID_ADDRESS),
+            Err(FlushError::TooLarge) => error(SBI_ERR_INVALID_PARAM),
+        },
+        _ => error(SBI_ERR_NOT_SUPPORTED),
+    }
+}
+
+#[inline]
+fn ecc_error(kind: usize) -> Option<EccError> {
+    EccError::ALL.get(kind).copied()
+}
+
+#[inline]
+fn error(error: usize) -> SbiRet {
+    SbiRet { error, value: 0 }
+}
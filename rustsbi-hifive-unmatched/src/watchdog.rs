@@ -0,0 +1,138 @@
+// 核的存活检测
+//
+// 打开`watchdog`特性时，每个核用固件定时器定时记录心跳（当时的mtime），并记录S态的状态：
+// 陷入时的mepc、sp和ra，以及S态有没有打开了却一直没有处理的中断。
+// M态时钟中断在S态关闭中断时照样到来，所以心跳本身只说明这个核的M态还在运行；
+// S态卡在关闭中断的状态时，到期的定时器中断（mip.STIP）、核间中断（mip.SSIP）或外部中断（mip.SEIP）
+// 会一直等待处理，心跳记录下它开始等待的时刻。S态空闲时在wfi中等待，没有等待处理的中断，不会被误报。
+//
+// 第MONITOR_HART个核在记录自己的心跳时检查所有已经启动的核，发现某个核超过HEARTBEAT_TIMEOUT没有心跳，
+// 或者S态超过HEARTBEAT_TIMEOUT没有处理等待的中断时，按这个核最后一次心跳记录的状态打印报告。
+// 卡住的核可能连M态中断也不响应，报告由监视核打印，不需要卡住的核配合。
+use crate::console::eprintln;
+use crate::hart_local::{self, HartLocal, HSM_STARTED};
+use crate::platform::{self, HART_COUNT, MAX_HART_ID};
+use crate::timer::{self, FirmwareTimer};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use riscv::register::{mip, sie};
+
+/// 检查其它核的核；第0个核没有S态，操作系统通常不启动它，由第1个核检查
+const MONITOR_HART: usize = 1;
+
+/// 记录心跳的间隔，mtime计数的1秒
+const HEARTBEAT_INTERVAL: u64 = platform::TIMEBASE_FREQUENCY;
+/// 超过这个时间没有心跳，或者S态没有处理等待的中断时报告
+const HEARTBEAT_TIMEOUT: u64 = 3 * HEARTBEAT_INTERVAL;
+
+// S态的定时器、软件和外部中断在mip和sie中的位
+const SUPERVISOR_INTERRUPTS: usize = (1 << 1) | (1 << 5) | (1 << 9);
+
+// 每个核最后一次心跳时的mtime，0表示还没有记录过
+static HEARTBEATS: [AtomicU64; HART_COUNT] = [const { AtomicU64::new(0) }; HART_COUNT];
+// S态开始有打开了却没有处理的中断时的mtime，0表示没有等待处理的中断
+static STALLED_SINCE: [AtomicU64; HART_COUNT] = [const { AtomicU64::new(0) }; HART_COUNT];
+// 最后一次心跳时S态的状态，报告时使用
+static SNAPSHOTS: [Snapshot; HART_COUNT] = [const { Snapshot::new() }; HART_COUNT];
+// 是否已经报告过这个核，恢复正常后由监视核清除，避免重复报告
+static REPORTED: [AtomicBool; HART_COUNT] = [const { AtomicBool::new(false) }; HART_COUNT];
+
+struct Snapshot {
+    mepc: AtomicUsize,
+    sp: AtomicUsize,
+    ra: AtomicUsize,
+    /// 打开了却没有处理的S态中断，按mip中的位
+    pending: AtomicUsize,
+}
+
+impl Snapshot {
+    const fn new() -> Self {
+        Snapshot {
+            mepc: AtomicUsize::new(0),
+            sp: AtomicUsize::new(0),
+            ra: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// 开始记录当前核的心跳
+pub fn init_hart(local: &mut HartLocal) {
+    heartbeat(local);
+}
+
+// 固件定时器的回调：记录心跳和S态的状态，设置下一次心跳，监视核再检查其它核
+fn heartbeat(local: &mut HartLocal) {
+    let now = local.clint.get_mtime();
+    let hart_id = local.hart_id;
+    let pending = mip::read().bits() & sie::read().bits() & SUPERVISOR_INTERRUPTS;
+    let snapshot = &SNAPSHOTS[hart_id];
+    snapshot.mepc.store(local.context.mepc, Ordering::Relaxed);
+    snapshot.sp.store(local.context.sp, Ordering::Relaxed);
+    snapshot.ra.store(local.context.ra, Ordering::Relaxed);
+    snapshot.pending.store(pending, Ordering::Relaxed);
+    if pending == 0 {
+        STALLED_SINCE[hart_id].store(0, Ordering::Relaxed);
+    } else if STALLED_SINCE[hart_id].load(Ordering::Relaxed) == 0 {
+        STALLED_SINCE[hart_id].store(now, Ordering::Relaxed);
+    }
+    // 监视核读到新的心跳时，一定能看到同一次心跳记录的状态
+    HEARTBEATS[hart_id].store(now, Ordering::Release);
+    timer::schedule(
+        local,
+        FirmwareTimer::Heartbeat,
+        now + HEARTBEAT_INTERVAL,
+        heartbeat,
+    );
+    if hart_id == MONITOR_HART {
+        check_harts(now);
+    }
+}
+
+// 检查所有已经启动的核，包括监视核自己的S态
+fn check_harts(now: u64) {
+    for hart_id in 0..=MAX_HART_ID {
+        if hart_local::hsm_state(hart_id) != HSM_STARTED {
+            continue;
+        }
+        let last = HEARTBEATS[hart_id].load(Ordering::Acquire);
+        if last == 0 {
+            continue;
+        }
+        let stalled_since = STALLED_SINCE[hart_id].load(Ordering::Relaxed);
+        let missed = now.saturating_sub(last) >= HEARTBEAT_TIMEOUT;
+        let stalled = stalled_since != 0 && now.saturating_sub(stalled_since) >= HEARTBEAT_TIMEOUT;
+        if !missed && !stalled {
+            REPORTED[hart_id].store(false, Ordering::Relaxed);
+            continue;
+        }
+        if REPORTED[hart_id].swap(true, Ordering::Relaxed) {
+            continue;
+        }
+        if missed {
+            eprintln!(
+                "[rustsbi-watchdog] hart {} missed heartbeats, last heartbeat at mtime {:#x}, now {:#x}",
+                hart_id, last, now
+            );
+        } else {
+            eprintln!(
+                "[rustsbi-watchdog] hart {} supervisor has not handled pending interrupts since mtime {:#x}, now {:#x}",
+                hart_id, stalled_since, now
+            );
+        }
+        report(hart_id, last);
+    }
+}
+
+// 按第hart_id个核最后一次心跳记录的状态打印报告
+fn report(hart_id: usize, last: u64) {
+    let snapshot = &SNAPSHOTS[hart_id];
+    eprintln!(
+        "[rustsbi-watchdog] hart {} at mtime {:#x}: mepc {:#x}, sp {:#x}, ra {:#x}, pending supervisor interrupts {:#x}",
+        hart_id,
+        last,
+        snapshot.mepc.load(Ordering::Relaxed),
+        snapshot.sp.load(Ordering::Relaxed),
+        snapshot.ra.load(Ordering::Relaxed),
+        snapshot.pending.load(Ordering::Relaxed)
+    );
+}
@@ -0,0 +1,8 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // fuzzing parameters are compiled in, see src/main.rs
+    println!("cargo:rerun-if-env-changed=SBI_FUZZ_SEED");
+    println!("cargo:rerun-if-env-changed=SBI_FUZZ_CALLS");
+    println!("cargo:rerun-if-env-changed=SBI_FUZZ_VERBOSE");
+    println!("cargo:rustc-link-arg=-Tsbi-fuzzer/src/linker64.ld");
+}
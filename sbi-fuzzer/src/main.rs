@@ -0,0 +1,246 @@
+//! SBI call fuzzer
+//!
+//! Issues pseudo-random SBI calls and checks that every call returns: a2-a7 are preserved,
+//! the error code is one defined by the specification, and extensions that are not
+//! advertised return SBI_ERR_NOT_SUPPORTED. The firmware must not panic or hang whatever
+//! the supervisor passes in.
+//!
+//! Parameters are compiled in from environment variables, `cargo xtask fuzz` sets them:
+//! `SBI_FUZZ_SEED` (decimal or 0x-prefixed hex), `SBI_FUZZ_CALLS` and `SBI_FUZZ_VERBOSE`,
+//! which prints every call before it is made so the last line before a crash is the culprit.
+#![no_std]
+#![no_main]
+
+mod console;
+mod rng;
+mod sbi;
+
+use rng::Rng;
+
+const DEFAULT_SEED: u64 = 0x5eed;
+const DEFAULT_CALLS: u64 = 100_000;
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+const MAX_HART_ID: usize = 4;
+
+pub extern "C" fn rust_main(hartid: usize, _dtb_pa: usize) -> ! {
+    if hartid != 0 {
+        park()
+    }
+    let seed = parse_env("SBI_FUZZ_SEED", option_env!("SBI_FUZZ_SEED")).unwrap_or(DEFAULT_SEED);
+    let calls = parse_env("SBI_FUZZ_CALLS", option_env!("SBI_FUZZ_CALLS")).unwrap_or(DEFAULT_CALLS);
+    let verbose = option_env!("SBI_FUZZ_VERBOSE").is_some();
+    println!(
+        "<< Sbi-fuzzer: seed = {:#x}, {} calls, verbose = {}",
+        seed, calls, verbose
+    );
+    let mut rng = Rng::new(seed);
+    let (mut skipped, mut failed) = (0, 0);
+    for index in 0..calls {
+        let (extension, function, mut args) = random_call(&mut rng);
+        if !sanitize(extension, function, &mut args) {
+            skipped += 1;
+            continue;
+        }
+        if verbose {
+            println!(
+                ">> #{}: eid {:#x} fid {:#x} args {:x?}",
+                index, extension, function, args
+            );
+        }
+        if let Err(reason) = check_call(extension, function, args) {
+            failed += 1;
+            println!(
+                "!! [FAIL] #{}: eid {:#x} fid {:#x} args {:x?}: {}",
+                index, extension, function, args, reason
+            );
+        }
+        if (index + 1) % PROGRESS_INTERVAL == 0 {
+            println!("<< Sbi-fuzzer: {} calls, {} failed", index + 1, failed);
+        }
+    }
+    println!(
+        "sbi-fuzzer-result: seed={:#x} calls={} skipped={} failed={}",
+        seed, calls, skipped, failed
+    );
+    if failed == 0 {
+        println!("<< Sbi-fuzzer: All calls returned valid results, SUCCESS");
+    } else {
+        println!("!! Sbi-fuzzer: {} calls FAILED", failed);
+    }
+    sbi::shutdown()
+}
+
+// Unset values fall back to the defaults; a value that does not parse is a build mistake,
+// and silently using the default would make the run impossible to reproduce from its log
+fn parse_env(name: &str, value: Option<&str>) -> Option<u64> {
+    let value = value?.trim();
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    match parsed {
+        Some(parsed) => Some(parsed),
+        None => panic!("invalid {}: {:?}", name, value),
+    }
+}
+
+const KNOWN_EXTENSIONS: [usize; 8] = [
+    sbi::EXTENSION_BASE,
+    sbi::EXTENSION_TIMER,
+    sbi::EXTENSION_IPI,
+    sbi::EXTENSION_RFENCE,
+    sbi::EXTENSION_HSM,
+    sbi::EXTENSION_PMU,
+    sbi::EXTENSION_DBCN,
+    sbi::EXTENSION_SRST,
+];
+
+// Addresses that make interesting pointers: null, unmapped, MMIO, firmware, payload, top of RAM
+const POINTERS: [usize; 8] = [
+    0x0,
+    0x1000,
+    0x0200_0000,
+    0x0c00_0000,
+    0x1001_0000,
+    0x8000_0000,
+    0x8020_0000,
+    0x4_7fff_fff8,
+];
+
+const BOUNDARIES: [usize; 7] = [
+    0,
+    1,
+    usize::MAX,
+    usize::MAX - 1,
+    1 << 63,
+    0x7fff_ffff,
+    0x8000_0000,
+];
+
+fn random_call(rng: &mut Rng) -> (usize, usize, [usize; 6]) {
+    let extension = match rng.below(10) {
+        0..=5 => rng.choose(&KNOWN_EXTENSIONS),
+        6 => rng.below(sbi::LEGACY_MAX + 1),
+        // vendor and firmware specific ranges
+        7 => 0x0900_0000 | rng.below(0x100),
+        8 => 0x0A00_0000 | rng.below(0x100),
+        _ => rng.next_usize(),
+    };
+    let function = match rng.below(4) {
+        0..=2 => rng.below(16),
+        _ => rng.next_usize(),
+    };
+    let mut args = [0; 6];
+    for arg in &mut args {
+        *arg = random_arg(rng);
+    }
+    (extension, function, args)
+}
+
+fn random_arg(rng: &mut Rng) -> usize {
+    match rng.below(8) {
+        0 => rng.below(16),
+        // hart IDs, including ones that do not exist
+        1 => rng.below(MAX_HART_ID + 3),
+        // hart masks
+        2 => rng.below(1 << (MAX_HART_ID + 2)),
+        3 => rng.choose(&BOUNDARIES),
+        // pointers, possibly misaligned
+        4 => rng.choose(&POINTERS) + rng.below(16),
+        5 => 0x8000_0000 + rng.below(0x4_0000_0000),
+        _ => rng.next_usize(),
+    }
+}
+
+/// Returns false for calls that must not be made, adjusts arguments of calls that would
+/// touch the fuzzer's own memory or its output
+fn sanitize(extension: usize, function: usize, args: &mut [usize; 6]) -> bool {
+    match (extension, function) {
+        // system reset and shutdown end the run
+        (sbi::EXTENSION_SRST, _) | (sbi::LEGACY_SHUTDOWN, _) => false,
+        // hart_start would run other harts at random addresses; hart_stop and
+        // hart_suspend may never return to the fuzzer
+        (sbi::EXTENSION_HSM, 0x0 | 0x1 | 0x3) => false,
+        // console_getchar waits for input
+        (sbi::LEGACY_CONSOLE_GETCHAR, _) => false,
+        // random bytes on the console would garble the log that decides the verdict
+        (sbi::LEGACY_CONSOLE_PUTCHAR, _) | (sbi::EXTENSION_DBCN, 0x2) => false,
+        // console_write and console_read keep their bad pointers, but with a length
+        // of zero they neither print memory nor overwrite the fuzzer
+        (sbi::EXTENSION_DBCN, 0x0 | 0x1) => {
+            args[0] = 0;
+            true
+        }
+        // snapshot_set_shmem lets the SBI write to memory at any time
+        (sbi::EXTENSION_PMU, 0x7) => false,
+        _ => true,
+    }
+}
+
+fn check_call(extension: usize, function: usize, args: [usize; 6]) -> Result<(), &'static str> {
+    let outcome = sbi::call(extension, function, args);
+    let mut expected = [0; 6];
+    expected[..4].copy_from_slice(&args[2..]);
+    expected[4] = function;
+    expected[5] = extension;
+    if outcome.preserved != expected {
+        return Err("a2-a7 not preserved");
+    }
+    if extension <= sbi::LEGACY_MAX {
+        // legacy calls return an implementation defined a0
+        return Ok(());
+    }
+    if !outcome.ret.is_valid_error() {
+        return Err("error code is not defined by the specification");
+    }
+    let probe = sbi::probe_extension(extension);
+    if !probe.is_valid_error() || probe.error != sbi::SBI_SUCCESS {
+        return Err("probe_extension failed");
+    }
+    if probe.value == 0 && outcome.ret.error != sbi::SBI_ERR_NOT_SUPPORTED {
+        return Err("extension not available but call did not return SBI_ERR_NOT_SUPPORTED");
+    }
+    Ok(())
+}
+
+fn park() -> ! {
+    loop {
+        unsafe { riscv::asm::wfi() }
+    }
+}
+
+use core::panic::PanicInfo;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("!! Sbi-fuzzer: {}", info);
+    println!("!! Sbi-fuzzer: FAILED due to panic");
+    // no failed= field, `cargo xtask test` reports it as a failure
+    println!("sbi-fuzzer-result: panic");
+    sbi::shutdown()
+}
+
+const BOOT_STACK_SIZE: usize = 0x4000 * (MAX_HART_ID + 1);
+
+static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
+
+#[unsafe(naked)]
+#[link_section = ".text.entry"]
+#[export_name = "_start"]
+unsafe extern "C" fn entry() -> ! {
+    core::arch::naked_asm!("
+    # sp = bootstack + (hartid + 1) * 0x4000
+    add     t0, a0, 1
+    slli    t0, t0, 14
+1:  auipc   sp, %pcrel_hi({boot_stack})
+    addi    sp, sp, %pcrel_lo(1b)
+    add     sp, sp, t0
+1:  auipc   t0, %pcrel_hi({rust_main})
+    addi    t0, t0, %pcrel_lo(1b)
+    jr      t0
+    ",
+    boot_stack = sym BOOT_STACK,
+    rust_main = sym rust_main,
+    )
+}
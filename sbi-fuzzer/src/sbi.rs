@@ -0,0 +1,91 @@
+use core::arch::asm;
+
+pub const EXTENSION_BASE: usize = 0x10;
+pub const EXTENSION_TIMER: usize = 0x54494D45;
+pub const EXTENSION_IPI: usize = 0x735049;
+pub const EXTENSION_RFENCE: usize = 0x52464E43;
+pub const EXTENSION_HSM: usize = 0x48534D;
+pub const EXTENSION_SRST: usize = 0x53525354;
+pub const EXTENSION_PMU: usize = 0x504D55;
+pub const EXTENSION_DBCN: usize = 0x4442434E;
+
+/// Legacy extensions use IDs 0x00 to 0x0F and return only a0
+pub const LEGACY_MAX: usize = 0x0F;
+pub const LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+pub const LEGACY_CONSOLE_GETCHAR: usize = 0x02;
+pub const LEGACY_SHUTDOWN: usize = 0x08;
+
+const FUNCTION_BASE_PROBE_EXTENSION: usize = 0x3;
+const FUNCTION_SYSTEM_RESET: usize = 0x0;
+
+pub const SBI_SUCCESS: usize = 0;
+pub const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
+// Smallest error code defined by the SBI specification, SBI_ERR_IO
+const SBI_ERR_MIN: isize = -13;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SbiRet {
+    pub error: usize,
+    pub value: usize,
+}
+
+impl SbiRet {
+    /// Whether the error field is one of the codes defined by the specification
+    pub fn is_valid_error(&self) -> bool {
+        let error = self.error as isize;
+        (SBI_ERR_MIN..=0).contains(&error)
+    }
+}
+
+/// Outcome of a raw SBI call, with the argument registers as they are after the call
+pub struct Outcome {
+    pub ret: SbiRet,
+    /// a2 to a7 after the call; the SBI must preserve every register except a0 and a1
+    pub preserved: [usize; 6],
+}
+
+#[inline(never)]
+pub fn call(extension: usize, function: usize, args: [usize; 6]) -> Outcome {
+    let (error, value);
+    let (mut a2, mut a3, mut a4, mut a5) = (args[2], args[3], args[4], args[5]);
+    let (mut a6, mut a7) = (function, extension);
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => error, inlateout("a1") args[1] => value,
+            inlateout("a2") a2, inlateout("a3") a3, inlateout("a4") a4, inlateout("a5") a5,
+            inlateout("a6") a6, inlateout("a7") a7,
+        )
+    };
+    Outcome {
+        ret: SbiRet { error, value },
+        preserved: [a2, a3, a4, a5, a6, a7],
+    }
+}
+
+pub fn probe_extension(extension_id: usize) -> SbiRet {
+    call(
+        EXTENSION_BASE,
+        FUNCTION_BASE_PROBE_EXTENSION,
+        [extension_id, 0, 0, 0, 0, 0],
+    )
+    .ret
+}
+
+pub fn console_putchar(c: usize) {
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") c => _,
+            in("a7") LEGACY_CONSOLE_PUTCHAR,
+        )
+    };
+}
+
+pub fn shutdown() -> ! {
+    call(EXTENSION_SRST, FUNCTION_SYSTEM_RESET, [0; 6]);
+    call(LEGACY_SHUTDOWN, 0, [0; 6]);
+    loop {
+        unsafe { riscv::asm::wfi() }
+    }
+}
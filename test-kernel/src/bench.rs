@@ -0,0 +1,47 @@
+//! Cost of the most frequent traps into the SBI, measured in cycles
+//!
+//! `rdtime` and `set_timer` are handled by the firmware's fast trap path, `get_spec_version`
+//! goes through the full register save and dispatch. The difference is what the fast path
+//! saves on every trap. Results are informational and never fail the suite.
+use crate::println;
+use crate::sbi;
+use riscv::register::{cycle, time};
+
+const ITERATIONS: u64 = 1000;
+
+// Average cycles of one call of `f`
+fn measure(mut f: impl FnMut()) -> u64 {
+    // warm up caches and branch predictors
+    for _ in 0..16 {
+        f();
+    }
+    let start = cycle::read64();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    (cycle::read64() - start) / ITERATIONS
+}
+
+pub fn run() {
+    let rdtime = measure(|| {
+        time::read64();
+    });
+    let set_timer = measure(|| {
+        sbi::set_timer(u64::MAX);
+    });
+    let full_path = measure(|| {
+        sbi::get_spec_version();
+    });
+    println!(
+        "<< Test-kernel: trap cost in cycles: rdtime {}, set_timer {}, get_spec_version (full path) {}",
+        rdtime, set_timer, full_path
+    );
+    println!(
+        "test-kernel-bench: rdtime={} set_timer={} full_path={} rdtime_saved={} set_timer_saved={}",
+        rdtime,
+        set_timer,
+        full_path,
+        full_path.saturating_sub(rdtime),
+        full_path.saturating_sub(set_timer)
+    );
+}
@@ -0,0 +1,88 @@
+use super::FUNCTION_UNKNOWN;
+use crate::println;
+use crate::sbi::{self, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::Suite;
+
+// Extension ID that is not assigned by the specification
+const EXTENSION_UNKNOWN: usize = 0x7FFF_FFFF;
+
+/// Returns false if the base extension is missing
+pub fn test(suite: &mut Suite) -> bool {
+    suite.group("base");
+    let probe = sbi::probe_extension(sbi::EXTENSION_BASE);
+    if !suite.expect_value("probe_base", probe, |version| version != 0) {
+        println!(
+            "!! Test-kernel: This SBI implementation may only have legacy extension implemented"
+        );
+        return false;
+    }
+    let spec_version = sbi::get_spec_version();
+    // bit 31 is reserved; the base extension is only defined since v0.2
+    suite.expect_value("get_spec_version", spec_version, |version| {
+        version & (1 << 31) == 0 && version >= 0x2
+    });
+    println!(
+        "<< Test-kernel: SBI specification version: v{}.{}",
+        (spec_version.value >> 24) & 0x7f,
+        spec_version.value & 0xff_ffff
+    );
+    let impl_id = sbi::get_sbi_impl_id();
+    suite.expect_value("get_sbi_impl_id", impl_id, |_| true);
+    println!(
+        "<< Test-kernel: SBI implementation: {}",
+        impl_name(impl_id.value)
+    );
+    suite.expect_value("get_sbi_impl_version", sbi::get_sbi_impl_version(), |_| {
+        true
+    });
+    suite.expect_value("get_mvendorid", sbi::get_mvendorid(), |_| true);
+    suite.expect_value("get_marchid", sbi::get_marchid(), |_| true);
+    suite.expect_value("get_mimpid", sbi::get_mimpid(), |_| true);
+    suite.expect_value(
+        "probe_unknown",
+        sbi::probe_extension(EXTENSION_UNKNOWN),
+        |available| available == 0,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_BASE, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+    suite.expect(
+        "unknown_extension",
+        sbi::sbi_call(EXTENSION_UNKNOWN, 0, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+    for (extension_id, name) in [
+        (sbi::EXTENSION_TIMER, "TIME"),
+        (sbi::EXTENSION_IPI, "IPI"),
+        (sbi::EXTENSION_RFENCE, "RFENCE"),
+        (sbi::EXTENSION_HSM, "HSM"),
+        (sbi::EXTENSION_SRST, "SRST"),
+        (sbi::EXTENSION_PMU, "PMU"),
+        (sbi::EXTENSION_DBCN, "DBCN"),
+    ] {
+        let ret = sbi::probe_extension(extension_id);
+        if ret.error == SBI_SUCCESS {
+            println!(
+                "<< Test-kernel: Extension {} available: {}",
+                name,
+                ret.value != 0
+            );
+        }
+    }
+    true
+}
+
+fn impl_name(impl_id: usize) -> &'static str {
+    match impl_id {
+        0 => "Berkeley Boot Loader (BBL)",
+        1 => "OpenSBI",
+        2 => "Xvisor",
+        3 => "KVM",
+        4 => "RustSBI",
+        5 => "Diosix",
+        6 => "Coffer",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,41 @@
+use super::FUNCTION_UNKNOWN;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::Suite;
+
+const FUNCTION_DBCN_CONSOLE_WRITE: usize = 0x0;
+
+pub fn test(suite: &mut Suite) {
+    suite.group("dbcn");
+    if !suite.require_extension(sbi::EXTENSION_DBCN) {
+        return;
+    }
+    let message = b"<< Test-kernel: written by DBCN console_write";
+    suite.expect_value("console_write", sbi::console_write(message), |written| {
+        written <= message.len()
+    });
+    suite.expect(
+        "console_write_byte",
+        sbi::console_write_byte(b'\n'),
+        SBI_SUCCESS,
+    );
+    // reading never blocks, it may or may not return a byte
+    let mut buf = [0u8; 1];
+    suite.expect_value("console_read", sbi::console_read(&mut buf), |read| {
+        read <= buf.len()
+    });
+    // a non-zero upper half puts the buffer above the physical address space
+    suite.expect(
+        "console_write_invalid_address",
+        sbi::sbi_call(
+            sbi::EXTENSION_DBCN,
+            FUNCTION_DBCN_CONSOLE_WRITE,
+            [message.len(), message.as_ptr() as usize, 1, 0, 0, 0],
+        ),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_DBCN, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
@@ -0,0 +1,53 @@
+//! Behaviour of the firmware outside SBI calls: instruction emulation and trap delegation
+use super::SPIN_LIMIT;
+use crate::suite::{self, Suite};
+use riscv::register::{cycle, hpmcounter3, instret, time};
+
+// scause of an illegal instruction exception
+const CAUSE_ILLEGAL_INSTRUCTION: usize = 2;
+
+pub fn test(suite: &mut Suite) {
+    suite.group("emulation");
+    let time_start = time::read64();
+    let advanced = suite::wait_until(SPIN_LIMIT, || time::read64() > time_start);
+    suite.check(
+        "rdtime",
+        advanced,
+        format_args!("{:#x} -> {:#x}", time_start, time::read64()),
+    );
+    // counters are either exposed through mcounteren or emulated, reading them never traps
+    crate::take_trap_cause();
+    let (cycle_start, instret_start) = (cycle::read64(), instret::read64());
+    let counted = suite::wait_until(SPIN_LIMIT, || {
+        cycle::read64() > cycle_start && instret::read64() > instret_start
+    });
+    let cause = crate::take_trap_cause();
+    suite.check(
+        "rdcycle_rdinstret",
+        counted && cause.is_none(),
+        format_args!(
+            "cycle {:#x} -> {:#x}, instret {:#x} -> {:#x}, scause {:x?}",
+            cycle_start,
+            cycle::read64(),
+            instret_start,
+            instret::read64(),
+            cause
+        ),
+    );
+    let hpmcounter = hpmcounter3::read();
+    let cause = crate::take_trap_cause();
+    suite.check(
+        "hpmcounter",
+        cause.is_none(),
+        format_args!("hpmcounter3 = {:#x}, scause {:x?}", hpmcounter, cause),
+    );
+    crate::take_trap_cause();
+    // mcycle cannot be written, this is always a 4-byte illegal instruction
+    unsafe { core::arch::asm!("csrw mcycle, x0") };
+    let cause = crate::take_trap_cause();
+    suite.check(
+        "illegal_instruction_delegated",
+        cause == Some(CAUSE_ILLEGAL_INSTRUCTION),
+        format_args!("scause {:x?}", cause),
+    );
+}
@@ -0,0 +1,119 @@
+//! Running tests on other harts
+//!
+//! Secondary harts become workers: they enable supervisor interrupts and poll a mailbox
+//! for jobs from the boot hart. Without HSM they turn into workers right after boot;
+//! with HSM they are stopped at boot and started as workers after the HSM tests.
+use super::SPIN_LIMIT;
+use crate::sbi::{self, SBI_SUCCESS};
+use crate::suite::{self, Suite};
+use crate::util::AmoMutex;
+use crate::MAX_HART_ID;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::{
+    sie, sstatus,
+    stvec::{self, TrapMode},
+};
+
+/// Opaque value of hart_start that turns the started hart into a worker
+pub const WORKER_OPAQUE: usize = 0x574f_524b;
+
+/// A test that runs on one hart; the second argument is the current hart ID
+pub type Job = fn(&mut Suite, usize);
+
+#[derive(Clone, Copy)]
+struct Call {
+    job: Job,
+    suite: *mut Suite,
+}
+
+// The suite is only used by the worker while the boot hart waits in `run_on`
+unsafe impl Send for Call {}
+
+static MAILBOX: AmoMutex<[Option<Call>; MAX_HART_ID + 1]> = AmoMutex::new([None; MAX_HART_ID + 1]);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DONE_INIT: AtomicBool = AtomicBool::new(false);
+
+static DONE: [AtomicBool; MAX_HART_ID + 1] = [DONE_INIT; MAX_HART_ID + 1];
+
+// Bit mask of harts that are running as workers
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+/// Take supervisor software and timer interrupts on the current hart
+pub fn enable_interrupts() {
+    unsafe {
+        stvec::write(crate::start_trap as *const () as usize, TrapMode::Direct);
+        sie::set_ssoft();
+        sstatus::set_sie();
+    }
+}
+
+pub fn worker_main(hartid: usize) -> ! {
+    enable_interrupts();
+    ONLINE.fetch_or(1 << hartid, Ordering::AcqRel);
+    loop {
+        let call = MAILBOX.lock()[hartid].take();
+        if let Some(call) = call {
+            (call.job)(unsafe { &mut *call.suite }, hartid);
+            DONE[hartid].store(true, Ordering::Release);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Start all secondary harts as workers; returns the mask of harts that can run jobs,
+/// including the boot hart
+pub fn bring_up(suite: &mut Suite, hartid: usize) -> usize {
+    suite.group("harts");
+    let all = (1 << (MAX_HART_ID + 1)) - 1;
+    ONLINE.fetch_or(1 << hartid, Ordering::AcqRel);
+    enable_interrupts();
+    if sbi::has_extension(sbi::EXTENSION_HSM) {
+        for target in (0..=MAX_HART_ID).filter(|&id| id != hartid) {
+            let ret = sbi::hart_get_status(target);
+            if ret.error == SBI_SUCCESS && ret.value == sbi::HART_STATE_STOPPED {
+                sbi::hart_start(
+                    target,
+                    crate::hsm_entry as *const () as usize,
+                    WORKER_OPAQUE,
+                );
+            }
+        }
+    }
+    suite::wait_until(SPIN_LIMIT, || ONLINE.load(Ordering::Acquire) == all);
+    let online = ONLINE.load(Ordering::Acquire);
+    suite.check(
+        "online",
+        online == all,
+        format_args!("expected harts {:#b}, online {:#b}", all, online),
+    );
+    online
+}
+
+/// Harts in the mask, in ascending order
+pub fn iter(mask: usize) -> impl Iterator<Item = usize> {
+    (0..=MAX_HART_ID).filter(move |id| mask & (1 << id) != 0)
+}
+
+/// Run `job` on the target hart and wait for it to finish
+///
+/// Returns false if the target hart does not pick up the job.
+pub fn run_on(suite: &mut Suite, hartid: usize, target: usize, job: Job) -> bool {
+    if target == hartid {
+        job(suite, hartid);
+        return true;
+    }
+    DONE[target].store(false, Ordering::Release);
+    MAILBOX.lock()[target] = Some(Call { job, suite });
+    if !suite::wait_until(SPIN_LIMIT, || DONE[target].load(Ordering::Acquire)) {
+        // not picked up yet: take it back, so that no one else uses the suite
+        if MAILBOX.lock()[target].take().is_some() {
+            return false;
+        }
+        // the job is running and finishes by itself
+        while !DONE[target].load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+    true
+}
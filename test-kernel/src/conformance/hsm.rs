@@ -0,0 +1,129 @@
+//! Hart state management
+//!
+//! All harts enter the kernel at boot. Secondary harts stop themselves if HSM is available,
+//! then the boot hart starts each of them at `hsm_entry` with an opaque value, waits for the
+//! hart to report it, and waits for the hart to stop again. Afterwards they are started
+//! once more as workers for the tests that need all harts.
+use super::{harts, FUNCTION_UNKNOWN, INVALID_HART_ID, SPIN_LIMIT};
+use crate::println;
+use crate::sbi::{
+    self, SBI_ERR_ALREADY_AVAILABLE, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS,
+};
+use crate::suite::{self, Suite};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const OPAQUE_MAGIC: usize = 0x4853_4d00;
+
+// Reserved suspend type, must be rejected
+const SUSPEND_TYPE_RESERVED: u32 = 0x0000_0001;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const OPAQUE_INIT: AtomicUsize = AtomicUsize::new(0);
+
+// Opaque value each hart received in a1 after hart_start
+static OPAQUE: [AtomicUsize; crate::MAX_HART_ID + 1] = [OPAQUE_INIT; crate::MAX_HART_ID + 1];
+
+/// Secondary harts end up here after boot; without HSM they become workers at once
+pub fn secondary_main(hartid: usize) -> ! {
+    if sbi::has_extension(sbi::EXTENSION_HSM) {
+        let ret = sbi::hart_stop();
+        println!(
+            "!! Test-kernel: Hart {} cannot stop itself: {:?}",
+            hartid, ret
+        );
+    }
+    harts::worker_main(hartid)
+}
+
+/// Harts started by `hart_start` in the HSM test jump here from `hsm_entry`
+pub extern "C" fn rust_hsm_main(hartid: usize, opaque: usize) -> ! {
+    if opaque == harts::WORKER_OPAQUE {
+        harts::worker_main(hartid)
+    }
+    OPAQUE[hartid].store(opaque, Ordering::Release);
+    sbi::hart_stop();
+    crate::park()
+}
+
+pub fn test(suite: &mut Suite, hartid: usize) {
+    suite.group("hsm");
+    if !suite.require_extension(sbi::EXTENSION_HSM) {
+        return;
+    }
+    let start_addr = crate::hsm_entry as *const () as usize;
+    suite.expect_value("get_status_self", sbi::hart_get_status(hartid), |state| {
+        state == sbi::HART_STATE_STARTED
+    });
+    suite.expect(
+        "get_status_invalid",
+        sbi::hart_get_status(INVALID_HART_ID),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "hart_start_self",
+        sbi::hart_start(hartid, start_addr, 0),
+        SBI_ERR_ALREADY_AVAILABLE,
+    );
+    suite.expect(
+        "hart_start_invalid",
+        sbi::hart_start(INVALID_HART_ID, start_addr, 0),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "hart_suspend_reserved_type",
+        sbi::hart_suspend(SUSPEND_TYPE_RESERVED, 0, 0),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_HSM, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+    for target in (0..=crate::MAX_HART_ID).filter(|&id| id != hartid) {
+        start_and_stop(suite, target, start_addr);
+    }
+}
+
+fn is_state(hartid: usize, state: usize) -> bool {
+    let ret = sbi::hart_get_status(hartid);
+    ret.error == SBI_SUCCESS && ret.value == state
+}
+
+fn start_and_stop(suite: &mut Suite, target: usize, start_addr: usize) {
+    let stopped = suite::wait_until(SPIN_LIMIT, || is_state(target, sbi::HART_STATE_STOPPED));
+    if !suite.check(
+        "stopped_after_boot",
+        stopped,
+        format_args!("hart {}: {:?}", target, sbi::hart_get_status(target)),
+    ) {
+        return;
+    }
+    let opaque = OPAQUE_MAGIC | target;
+    OPAQUE[target].store(0, Ordering::Release);
+    if !suite.expect(
+        "hart_start",
+        sbi::hart_start(target, start_addr, opaque),
+        SBI_SUCCESS,
+    ) {
+        return;
+    }
+    let arrived = suite::wait_until(SPIN_LIMIT, || {
+        OPAQUE[target].load(Ordering::Acquire) == opaque
+    });
+    suite.check(
+        "hart_start_opaque",
+        arrived,
+        format_args!(
+            "hart {}: expected {:#x}, got {:#x}",
+            target,
+            opaque,
+            OPAQUE[target].load(Ordering::Acquire)
+        ),
+    );
+    let stopped = suite::wait_until(SPIN_LIMIT, || is_state(target, sbi::HART_STATE_STOPPED));
+    suite.check(
+        "hart_stop",
+        stopped,
+        format_args!("hart {}: {:?}", target, sbi::hart_get_status(target)),
+    );
+}
@@ -0,0 +1,135 @@
+use super::{harts, FUNCTION_UNKNOWN, INVALID_HART_ID, SPIN_LIMIT};
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::{self, Suite};
+use crate::MAX_HART_ID;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use riscv::register::{sip, time};
+
+// Give up waiting for an IPI after this many ticks, one second with the 1MHz timebase
+const IPI_TIMEOUT: u64 = 1_000_000;
+// Wait this long after the expected IPIs arrived, to catch IPIs sent to wrong harts
+const IPI_SETTLE: u64 = 10_000;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const RECEIVED_INIT: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const RECEIVED_AT_INIT: AtomicU64 = AtomicU64::new(0);
+
+// Number and time of supervisor software interrupts taken on each hart
+static RECEIVED: [AtomicUsize; MAX_HART_ID + 1] = [RECEIVED_INIT; MAX_HART_ID + 1];
+static RECEIVED_AT: [AtomicU64; MAX_HART_ID + 1] = [RECEIVED_AT_INIT; MAX_HART_ID + 1];
+
+pub fn test(suite: &mut Suite, hartid: usize) {
+    suite.group("ipi");
+    if !suite.require_extension(sbi::EXTENSION_IPI) {
+        return;
+    }
+    // the same hart addressed relative to base 0 and relative to its own hart ID
+    for (name, hart_mask, hart_mask_base) in [
+        ("send_ipi_self", 1 << hartid, 0),
+        ("send_ipi_self_base", 1, hartid),
+    ] {
+        suite::clear_ssip();
+        if suite.expect(name, sbi::send_ipi(hart_mask, hart_mask_base), SBI_SUCCESS) {
+            suite.check(
+                "send_ipi_ssip",
+                suite::wait_until(SPIN_LIMIT, || sip::read().ssoft()),
+                format_args!("sip = {:#x}", sip::read().bits()),
+            );
+        }
+        suite::clear_ssip();
+    }
+    suite.expect(
+        "send_ipi_invalid_base",
+        sbi::send_ipi(1, INVALID_HART_ID),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_IPI, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
+
+/// Supervisor software interrupt handler
+pub fn on_ipi(hartid: usize) {
+    suite::clear_ssip();
+    RECEIVED_AT[hartid].store(time::read64(), Ordering::Release);
+    RECEIVED[hartid].fetch_add(1, Ordering::AcqRel);
+}
+
+/// Check that IPIs reach exactly the harts selected by hart_mask and hart_mask_base
+pub fn test_harts(suite: &mut Suite, online: usize) {
+    suite.group("ipi_delivery");
+    if !suite.require_extension(sbi::EXTENSION_IPI) {
+        return;
+    }
+    for target in harts::iter(online) {
+        deliver(suite, "single", 1 << target, 0, 1 << target);
+    }
+    for base in 1..=MAX_HART_ID {
+        let hart_mask = online >> base;
+        if hart_mask != 0 {
+            deliver(suite, "mask_base", hart_mask, base, hart_mask << base);
+        }
+    }
+    for pattern in [0b10101, 0b01010] {
+        deliver(suite, "alternate", online & pattern, 0, online & pattern);
+    }
+    deliver(suite, "all", online, 0, online);
+    // hart_mask_base of -1 addresses all harts since SBI v1.0
+    let spec_version = sbi::get_spec_version().value;
+    if (spec_version >> 24) & 0x7f >= 1 {
+        deliver(suite, "broadcast", 0, usize::MAX, online);
+    } else {
+        suite.skip("broadcast", "needs SBI v1.0");
+    }
+}
+
+fn received_mask() -> usize {
+    (0..=MAX_HART_ID)
+        .filter(|&id| RECEIVED[id].load(Ordering::Acquire) != 0)
+        .fold(0, |mask, id| mask | (1 << id))
+}
+
+fn deliver(
+    suite: &mut Suite,
+    name: &str,
+    hart_mask: usize,
+    hart_mask_base: usize,
+    expected: usize,
+) {
+    for received in &RECEIVED {
+        received.store(0, Ordering::Release);
+    }
+    let sent_at = time::read64();
+    let ret = sbi::send_ipi(hart_mask, hart_mask_base);
+    if !suite.expect("send_ipi", ret, SBI_SUCCESS) {
+        return;
+    }
+    while received_mask() & expected != expected && time::read64() < sent_at + IPI_TIMEOUT {
+        core::hint::spin_loop();
+    }
+    let settle = time::read64() + IPI_SETTLE;
+    while time::read64() < settle {
+        core::hint::spin_loop();
+    }
+    let received = received_mask();
+    let once = harts::iter(expected).all(|id| RECEIVED[id].load(Ordering::Acquire) == 1);
+    let latency = harts::iter(received)
+        .map(|id| {
+            RECEIVED_AT[id]
+                .load(Ordering::Acquire)
+                .wrapping_sub(sent_at)
+        })
+        .max()
+        .unwrap_or(0);
+    suite.check(
+        name,
+        received == expected && once,
+        format_args!(
+            "mask {:#b} base {:#x}: expected harts {:#b}, received {:#b}, max latency {} ticks",
+            hart_mask, hart_mask_base, expected, received, latency
+        ),
+    );
+}
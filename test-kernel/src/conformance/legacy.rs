@@ -0,0 +1,80 @@
+use super::SPIN_LIMIT;
+use crate::println;
+use crate::sbi::{self, SBI_SUCCESS};
+use crate::suite::{self, Suite};
+use riscv::register::sip;
+
+pub fn test(suite: &mut Suite, hartid: usize) {
+    suite.group("legacy");
+    // legacy extension IDs are the same as the legacy function numbers
+    let probe_failed = sbi::LEGACY_EXTENSIONS
+        .iter()
+        .find(|(extension_id, _)| sbi::probe_extension(*extension_id).error != SBI_SUCCESS);
+    suite.check(
+        "probe",
+        probe_failed.is_none(),
+        format_args!("first failing extension: {:?}", probe_failed),
+    );
+    for (extension_id, name) in sbi::LEGACY_EXTENSIONS {
+        println!(
+            "<< Test-kernel: Legacy {} available: {}",
+            name,
+            sbi::has_extension(extension_id)
+        );
+    }
+    if sbi::has_extension(sbi::SBI_CONSOLE_PUTCHAR) {
+        let mut error = 0;
+        for byte in b"<< Test-kernel: written by legacy console_putchar\n" {
+            error |= sbi::console_putchar(*byte as usize);
+        }
+        suite.check(
+            "console_putchar",
+            error == 0,
+            format_args!("a0 = {:#x}", error),
+        );
+    } else {
+        suite.skip("console_putchar", "extension not available");
+    }
+    if sbi::has_extension(sbi::SBI_SET_TIMER) {
+        let ret = sbi::legacy_set_timer(usize::MAX);
+        suite.check(
+            "set_timer_cancel",
+            ret == 0,
+            format_args!("a0 = {:#x}", ret),
+        );
+        suite.check(
+            "set_timer_stip_clear",
+            !sip::read().stimer(),
+            format_args!("sip = {:#x}", sip::read().bits()),
+        );
+    } else {
+        suite.skip("set_timer", "extension not available");
+    }
+    // legacy calls take the address of the hart mask
+    let hart_mask: usize = 1 << hartid;
+    if sbi::has_extension(sbi::SBI_SEND_IPI) {
+        suite::clear_ssip();
+        let ret = sbi::legacy_send_ipi(&hart_mask);
+        suite.check("send_ipi_self", ret == 0, format_args!("a0 = {:#x}", ret));
+        suite.check(
+            "send_ipi_ssip",
+            suite::wait_until(SPIN_LIMIT, || sip::read().ssoft()),
+            format_args!("sip = {:#x}", sip::read().bits()),
+        );
+        let ret = sbi::legacy_clear_ipi();
+        suite.check("clear_ipi", ret == 0, format_args!("a0 = {:#x}", ret));
+        suite::clear_ssip();
+    } else {
+        suite.skip("send_ipi", "extension not available");
+    }
+    if sbi::has_extension(sbi::SBI_REMOTE_FENCE_I) {
+        let ret = sbi::legacy_remote_fence_i(&hart_mask);
+        suite.check(
+            "remote_fence_i_self",
+            ret == 0,
+            format_args!("a0 = {:#x}", ret),
+        );
+    } else {
+        suite.skip("remote_fence_i", "extension not available");
+    }
+}
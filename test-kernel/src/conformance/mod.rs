@@ -0,0 +1,49 @@
+//! SBI conformance tests, one module per extension
+//!
+//! Tests run on the boot hart. Extensions that are not advertised by `probe_extension`
+//! are skipped, not failed; calls with invalid arguments must return the error code
+//! required by the SBI specification.
+mod base;
+mod dbcn;
+mod emulation;
+pub mod harts;
+pub mod hsm;
+pub mod ipi;
+mod legacy;
+mod pmu;
+mod rfence;
+mod srst;
+pub mod time;
+mod vendor;
+
+use crate::suite::Suite;
+
+/// Function ID that no extension defines
+pub const FUNCTION_UNKNOWN: usize = 0x7F;
+
+/// Hart ID that does not exist on the platform
+pub const INVALID_HART_ID: usize = crate::MAX_HART_ID + 1;
+
+/// Iterations to wait for an interrupt or another hart before giving up
+pub const SPIN_LIMIT: usize = 10_000_000;
+
+pub fn run(suite: &mut Suite, hartid: usize) {
+    if !base::test(suite) {
+        // without the base extension nothing else can be probed
+        return;
+    }
+    legacy::test(suite, hartid);
+    time::test(suite);
+    ipi::test(suite, hartid);
+    rfence::test(suite, hartid);
+    hsm::test(suite, hartid);
+    let online = harts::bring_up(suite, hartid);
+    time::test_harts(suite, hartid, online);
+    ipi::test_harts(suite, online);
+    pmu::test(suite);
+    dbcn::test(suite);
+    emulation::test(suite);
+    vendor::test(suite);
+    // system reset goes last, a broken implementation may reset the machine here
+    srst::test(suite);
+}
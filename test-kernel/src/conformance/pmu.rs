@@ -0,0 +1,37 @@
+use super::FUNCTION_UNKNOWN;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::Suite;
+
+pub fn test(suite: &mut Suite) {
+    suite.group("pmu");
+    if !suite.require_extension(sbi::EXTENSION_PMU) {
+        return;
+    }
+    let num_counters = sbi::pmu_num_counters();
+    if !suite.expect_value("num_counters", num_counters, |_| true) {
+        return;
+    }
+    let count = num_counters.value;
+    let failed = (0..count).find(|&idx| sbi::pmu_counter_get_info(idx).error != SBI_SUCCESS);
+    suite.check(
+        "counter_get_info",
+        failed.is_none(),
+        format_args!("{} counters, first failing index: {:?}", count, failed),
+    );
+    // counter indices are in [0, count)
+    suite.expect(
+        "counter_get_info_invalid",
+        sbi::pmu_counter_get_info(count),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "counter_stop_invalid",
+        sbi::pmu_counter_stop(count, 1, 0),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_PMU, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
@@ -0,0 +1,42 @@
+use super::{FUNCTION_UNKNOWN, INVALID_HART_ID};
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::Suite;
+
+pub fn test(suite: &mut Suite, hartid: usize) {
+    suite.group("rfence");
+    if !suite.require_extension(sbi::EXTENSION_RFENCE) {
+        return;
+    }
+    let hart_mask = 1 << hartid;
+    suite.expect(
+        "remote_fence_i_self",
+        sbi::remote_fence_i(hart_mask, 0),
+        SBI_SUCCESS,
+    );
+    // start 0 and size 0 flush the whole address space
+    suite.expect(
+        "remote_sfence_vma_self",
+        sbi::remote_sfence_vma(hart_mask, 0, 0, 0),
+        SBI_SUCCESS,
+    );
+    suite.expect(
+        "remote_sfence_vma_asid_self",
+        sbi::remote_sfence_vma_asid(hart_mask, 0, 0, 0, 0),
+        SBI_SUCCESS,
+    );
+    suite.expect(
+        "remote_fence_i_invalid_base",
+        sbi::remote_fence_i(1, INVALID_HART_ID),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "remote_sfence_vma_invalid_base",
+        sbi::remote_sfence_vma(1, INVALID_HART_ID, 0, 0),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_RFENCE, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
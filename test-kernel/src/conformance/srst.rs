@@ -0,0 +1,30 @@
+use super::FUNCTION_UNKNOWN;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED};
+use crate::suite::Suite;
+
+// Reserved reset type and reason, must be rejected
+const RESET_TYPE_RESERVED: usize = 0x1000_0000;
+const RESET_REASON_RESERVED: usize = 0x1000_0000;
+
+pub fn test(suite: &mut Suite) {
+    suite.group("srst");
+    if !suite.require_extension(sbi::EXTENSION_SRST) {
+        return;
+    }
+    // a valid reset is only exercised by the final shutdown
+    suite.expect(
+        "reset_reserved_type",
+        sbi::reset(RESET_TYPE_RESERVED, sbi::RESET_REASON_NO_REASON),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "reset_reserved_reason",
+        sbi::reset(sbi::RESET_TYPE_SHUTDOWN, RESET_REASON_RESERVED),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_SRST, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
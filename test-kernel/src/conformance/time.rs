@@ -0,0 +1,177 @@
+use super::{harts, FUNCTION_UNKNOWN};
+use crate::sbi::{self, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::suite::Suite;
+use crate::MAX_HART_ID;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use riscv::register::{sie, sip, time};
+
+// Timer deadlines are this many ticks ahead, 10ms with the 1MHz timebase
+const TIMER_DELTA: u64 = 10_000;
+// Give up waiting for a timer interrupt this many ticks after its deadline
+const TIMER_TIMEOUT: u64 = 1_000_000;
+
+const NOT_FIRED: u64 = u64::MAX;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const FIRED_AT_INIT: AtomicU64 = AtomicU64::new(NOT_FIRED);
+#[allow(clippy::declare_interior_mutable_const)]
+const FIRED_COUNT_INIT: AtomicUsize = AtomicUsize::new(0);
+
+// Time of the last supervisor timer interrupt on each hart
+static FIRED_AT: [AtomicU64; MAX_HART_ID + 1] = [FIRED_AT_INIT; MAX_HART_ID + 1];
+static FIRED_COUNT: [AtomicUsize; MAX_HART_ID + 1] = [FIRED_COUNT_INIT; MAX_HART_ID + 1];
+
+pub fn test(suite: &mut Suite) {
+    suite.group("time");
+    if !suite.require_extension(sbi::EXTENSION_TIMER) {
+        return;
+    }
+    // a deadline in the far future cancels the timer and clears a pending timer interrupt
+    suite.expect("set_timer_cancel", sbi::set_timer(u64::MAX), SBI_SUCCESS);
+    suite.check(
+        "set_timer_stip_clear",
+        !sip::read().stimer(),
+        format_args!("sip = {:#x}", sip::read().bits()),
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_TIMER, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
+
+/// Supervisor timer interrupt handler
+pub fn on_timer(hartid: usize) {
+    FIRED_AT[hartid].store(time::read64(), Ordering::Release);
+    FIRED_COUNT[hartid].fetch_add(1, Ordering::AcqRel);
+    // stop taking the interrupt until the next deadline is programmed
+    unsafe { sie::clear_stimer() };
+    sbi::set_timer(u64::MAX);
+}
+
+/// Program and cancel timers on every online hart
+pub fn test_harts(suite: &mut Suite, hartid: usize, online: usize) {
+    suite.group("time_interrupt");
+    if !suite.require_extension(sbi::EXTENSION_TIMER) {
+        return;
+    }
+    for target in harts::iter(online) {
+        let ran = harts::run_on(suite, hartid, target, timer_job);
+        suite.check("run_on_hart", ran, format_args!("hart {}", target));
+    }
+}
+
+fn reset(hartid: usize) {
+    FIRED_AT[hartid].store(NOT_FIRED, Ordering::Release);
+    FIRED_COUNT[hartid].store(0, Ordering::Release);
+}
+
+fn arm(deadline: u64) -> bool {
+    let ret = sbi::set_timer(deadline);
+    unsafe { sie::set_stimer() };
+    ret.error == SBI_SUCCESS
+}
+
+// Wait for the timer interrupt until `timeout`, returns the time it arrived
+fn wait_fired(hartid: usize, timeout: u64) -> Option<u64> {
+    loop {
+        match FIRED_AT[hartid].load(Ordering::Acquire) {
+            NOT_FIRED if time::read64() > timeout => return None,
+            NOT_FIRED => core::hint::spin_loop(),
+            fired_at => return Some(fired_at),
+        }
+    }
+}
+
+fn wait_ticks(ticks: u64) {
+    let until = time::read64() + ticks;
+    while time::read64() < until {
+        core::hint::spin_loop();
+    }
+}
+
+fn timer_job(suite: &mut Suite, hartid: usize) {
+    // interrupt arrives at or after the deadline, exactly once
+    reset(hartid);
+    let deadline = time::read64() + TIMER_DELTA;
+    let armed = arm(deadline);
+    let fired_at = wait_fired(hartid, deadline + TIMER_TIMEOUT);
+    wait_ticks(TIMER_DELTA);
+    let count = FIRED_COUNT[hartid].load(Ordering::Acquire);
+    suite.check(
+        "deadline",
+        armed && matches!(fired_at, Some(at) if at >= deadline) && count == 1,
+        format_args!(
+            "hart {}: deadline {:#x}, fired at {:x?} ({} times), latency {} ticks",
+            hartid,
+            deadline,
+            fired_at,
+            count,
+            fired_at.map_or(0, |at| at.wrapping_sub(deadline))
+        ),
+    );
+
+    // a deadline in the past fires immediately
+    reset(hartid);
+    let now = time::read64();
+    let armed = arm(0);
+    let fired_at = wait_fired(hartid, now + TIMER_TIMEOUT);
+    suite.check(
+        "deadline_passed",
+        armed && fired_at.is_some(),
+        format_args!(
+            "hart {}: fired at {:x?}, latency {} ticks",
+            hartid,
+            fired_at,
+            fired_at.map_or(0, |at| at.wrapping_sub(now))
+        ),
+    );
+
+    // re-arming to an earlier deadline replaces the later one
+    reset(hartid);
+    let now = time::read64();
+    let (early, late) = (now + TIMER_DELTA, now + 100 * TIMER_DELTA);
+    let armed = arm(late) && arm(early);
+    let fired_at = wait_fired(hartid, early + TIMER_TIMEOUT);
+    suite.check(
+        "rearm_earlier",
+        armed && matches!(fired_at, Some(at) if at >= early && at < late),
+        format_args!(
+            "hart {}: deadline {:#x}, fired at {:x?}",
+            hartid, early, fired_at
+        ),
+    );
+
+    // re-arming to a later deadline postpones the interrupt
+    reset(hartid);
+    let now = time::read64();
+    let (early, late) = (now + TIMER_DELTA, now + 3 * TIMER_DELTA);
+    let armed = arm(early) && arm(late);
+    let fired_at = wait_fired(hartid, late + TIMER_TIMEOUT);
+    suite.check(
+        "rearm_later",
+        armed && matches!(fired_at, Some(at) if at >= late),
+        format_args!(
+            "hart {}: deadline {:#x}, fired at {:x?}",
+            hartid, late, fired_at
+        ),
+    );
+
+    // setting u64::MAX cancels a programmed timer
+    reset(hartid);
+    let armed = arm(time::read64() + TIMER_DELTA);
+    let cancelled = sbi::set_timer(u64::MAX).error == SBI_SUCCESS;
+    wait_ticks(3 * TIMER_DELTA);
+    let fired_at = FIRED_AT[hartid].load(Ordering::Acquire);
+    suite.check(
+        "cancel",
+        armed && cancelled && fired_at == NOT_FIRED && !sip::read().stimer(),
+        format_args!(
+            "hart {}: fired at {:#x}, sip = {:#x}",
+            hartid,
+            fired_at,
+            sip::read().bits()
+        ),
+    );
+    unsafe { sie::clear_stimer() };
+}
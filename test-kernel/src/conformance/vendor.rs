@@ -0,0 +1,76 @@
+//! SiFive vendor extension: L2 cache configuration, ECC error statistics and cache maintenance
+use super::FUNCTION_UNKNOWN;
+use crate::sbi::{
+    self, SBI_ERR_INVALID_ADDRESS, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS,
+};
+use crate::suite::Suite;
+use core::ptr::addr_of_mut;
+
+// directory corrected, directory uncorrected, data corrected, data uncorrected
+const ECC_ERROR_KINDS: usize = 4;
+// UART0 registers, a device and not memory
+const DEVICE_ADDRESS: usize = 0x1001_0000;
+
+static mut FLUSH_BUFFER: [u8; 256] = [0; 256];
+
+pub fn test(suite: &mut Suite) {
+    suite.group("sifive");
+    // only advertised on platforms with an L2 cache controller
+    if !suite.require_extension(sbi::EXTENSION_SIFIVE) {
+        return;
+    }
+    // ways is the second byte of the Config register
+    suite.expect_value("l2_config", sbi::sifive_l2_config(), |config| {
+        (config >> 8) & 0xff != 0
+    });
+    let failed = (0..ECC_ERROR_KINDS).find(|&kind| {
+        sbi::sifive_l2_ecc_count(kind).error != SBI_SUCCESS
+            || sbi::sifive_l2_ecc_last_address(kind).error != SBI_SUCCESS
+    });
+    suite.check(
+        "l2_ecc",
+        failed.is_none(),
+        format_args!("first failing error kind: {:?}", failed),
+    );
+    suite.expect(
+        "l2_ecc_invalid_kind",
+        sbi::sifive_l2_ecc_count(ECC_ERROR_KINDS),
+        SBI_ERR_INVALID_PARAM,
+    );
+    let buffer = unsafe { &mut *addr_of_mut!(FLUSH_BUFFER) };
+    buffer.fill(0x5a);
+    suite.expect(
+        "l2_flush_range",
+        sbi::sifive_l2_flush_range(buffer.as_ptr() as usize, buffer.len()),
+        SBI_SUCCESS,
+    );
+    suite.check(
+        "l2_flush_range_data",
+        buffer.iter().all(|byte| *byte == 0x5a),
+        format_args!("buffer changed after flush"),
+    );
+    suite.expect(
+        "l2_flush_range_device",
+        sbi::sifive_l2_flush_range(DEVICE_ADDRESS, 64),
+        SBI_ERR_INVALID_ADDRESS,
+    );
+    // one byte more than the cache capacity, still inside memory
+    let config = sbi::sifive_l2_config().value;
+    let capacity =
+        ((config & 0xff) * ((config >> 8) & 0xff)) << (((config >> 16) & 0xff) + (config >> 24));
+    suite.expect(
+        "l2_flush_range_too_large",
+        sbi::sifive_l2_flush_range(buffer.as_ptr() as usize, capacity + 1),
+        SBI_ERR_INVALID_PARAM,
+    );
+    suite.expect(
+        "l2_flush_range_overflow",
+        sbi::sifive_l2_flush_range(buffer.as_ptr() as usize, usize::MAX),
+        SBI_ERR_INVALID_ADDRESS,
+    );
+    suite.expect(
+        "unknown_function",
+        sbi::sbi_call(sbi::EXTENSION_SIFIVE, FUNCTION_UNKNOWN, [0; 6]),
+        SBI_ERR_NOT_SUPPORTED,
+    );
+}
@@ -1,154 +1,93 @@
-#![feature(naked_functions, asm_sym, asm_const)]
-#![feature(default_alloc_error_handler)]
 #![no_std]
 #![no_main]
 
+mod bench;
+mod conformance;
 mod console;
 mod mm;
 mod sbi;
+mod suite;
 mod util;
 
+use core::sync::atomic::{AtomicUsize, Ordering};
 use riscv::register::{
-    scause::{self, Exception, Trap},
+    scause::{self, Interrupt, Trap},
     sepc,
     stvec::{self, TrapMode},
 };
 
+/// Largest hart ID on the platform; HiFive Unmatched and QEMU `-smp 5` both have harts 0-4
+pub const MAX_HART_ID: usize = 4;
+
+const BOOT_HART_ID: usize = 0;
+
 pub extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
-    if hartid == 0 {
-        // initialization
-        mm::init_heap();
-    }
-    if hartid == 0 {
-        println!(
-            "<< Test-kernel: Hart id = {}, DTB physical address = {:#x}",
-            hartid, dtb_pa
-        );
-        test_base_extension();
-        test_sbi_ins_emulation();
-        unsafe { stvec::write(start_trap as usize, TrapMode::Direct) };
-        println!(">> Test-kernel: Trigger illegal exception");
-        unsafe { core::arch::asm!("csrw mcycle, x0") }; // mcycle cannot be written, this is always a 4-byte illegal instruction
+    if hartid != BOOT_HART_ID {
+        conformance::hsm::secondary_main(hartid)
     }
-    if hartid == 0 {
-        let sbi_ret = sbi::hart_stop(3);
-        println!(">> Stop hart 3, return value {:?}", sbi_ret);
-        for i in 0..4 {
-            let sbi_ret = sbi::hart_get_status(i);
-            println!(">> Hart {} state return value: {:?}", i, sbi_ret);
-        }
-    } else if hartid == 1 {
-        let sbi_ret = sbi::hart_suspend(0x00000000, 0, 0);
-        println!(
-            ">> Start test for hart {}, retentive suspend return value {:?}",
-            hartid, sbi_ret
-        );
-    } else if hartid == 2 {
-        /* resume_addr should be physical address, and here pa == va */
-        let sbi_ret = sbi::hart_suspend(0x80000000, hart_2_resume as usize, 0x4567890a);
-        println!(">> Error for non-retentive suspend: {:?}", sbi_ret);
-        loop {}
+    // initialization
+    mm::init_heap();
+    println!(
+        "<< Test-kernel: Hart id = {}, DTB physical address = {:#x}",
+        hartid, dtb_pa
+    );
+    unsafe { stvec::write(start_trap as *const () as usize, TrapMode::Direct) };
+    let mut suite = suite::Suite::new();
+    conformance::run(&mut suite, hartid);
+    bench::run();
+    if suite.finish() {
+        println!("<< Test-kernel: All SBI tests SUCCESS, shutdown");
     } else {
-        // hartid == 3
-        loop {}
-    }
-    if hartid == 0 {
         println!(
-            "<< Test-kernel: test for hart {} success, wake another hart",
-            hartid
+            "!! Test-kernel: {} SBI tests FAILED, shutdown",
+            suite.failed()
         );
-        let bv: usize = 0b10;
-        let sbi_ret = sbi::send_ipi(&bv as *const _ as usize, hartid); // wake hartid + 1
-        println!(">> Wake hart 1, sbi return value {:?}", sbi_ret);
-        loop {} // wait for machine shutdown
-    } else if hartid == 1 {
-        // send software IPI to activate hart 2
-        let bv: usize = 0b10;
-        let sbi_ret = sbi::send_ipi(&bv as *const _ as usize, hartid); // wake hartid + 1
-        println!(">> Wake hart 2, sbi return value {:?}", sbi_ret);
-        loop {}
-    } else {
-        // hartid == 2 || hartid == 3
-        unreachable!()
     }
+    sbi::shutdown()
 }
 
-extern "C" fn hart_2_resume(hart_id: usize, param: usize) {
-    println!(
-        "<< The parameter passed to hart {} resume is: {:#x}",
-        hart_id, param
-    );
-    let param = 0x12345678;
-    println!(">> Start hart 3 with parameter {:#x}", param);
-    /* start_addr should be physical address, and here pa == va */
-    let sbi_ret = sbi::hart_start(3, hart_3_start as usize, param);
-    println!(">> SBI return value: {:?}", sbi_ret);
-    loop {} // wait for machine shutdown
+/// Wait for the machine to shut down
+pub fn park() -> ! {
+    loop {
+        unsafe { riscv::asm::wfi() }
+    }
 }
 
-extern "C" fn hart_3_start(hart_id: usize, param: usize) {
-    println!(
-        "<< The parameter passed to hart {} start is: {:#x}",
-        hart_id, param
-    );
-    println!("<< Test-kernel: All hart SBI test SUCCESS, shutdown");
-    sbi::shutdown()
-}
+const NO_TRAP: usize = usize::MAX;
 
-fn test_base_extension() {
-    println!(">> Test-kernel: Testing base extension");
-    let base_version = sbi::probe_extension(sbi::EXTENSION_BASE);
-    if base_version == 0 {
-        println!("!! Test-kernel: no base extension probed; SBI call returned value '0'");
-        println!(
-            "!! Test-kernel: This SBI implementation may only have legacy extension implemented"
-        );
-        println!("!! Test-kernel: SBI test FAILED due to no base extension found");
-        sbi::shutdown()
+// scause of the last exception taken, NO_TRAP if none
+static TRAP_CAUSE: AtomicUsize = AtomicUsize::new(NO_TRAP);
+
+/// Returns the scause of the exception taken since the last call, if any
+pub fn take_trap_cause() -> Option<usize> {
+    match TRAP_CAUSE.swap(NO_TRAP, Ordering::AcqRel) {
+        NO_TRAP => None,
+        cause => Some(cause),
     }
-    println!("<< Test-kernel: Base extension version: {:x}", base_version);
-    println!(
-        "<< Test-kernel: SBI specification version: {:x}",
-        sbi::get_spec_version()
-    );
-    println!(
-        "<< Test-kernel: SBI implementation Id: {:x}",
-        sbi::get_sbi_impl_id()
-    );
-    println!(
-        "<< Test-kernel: SBI implementation version: {:x}",
-        sbi::get_sbi_impl_version()
-    );
-    println!(
-        "<< Test-kernel: Device mvendorid: {:x}",
-        sbi::get_mvendorid()
-    );
-    println!("<< Test-kernel: Device marchid: {:x}", sbi::get_marchid());
-    println!("<< Test-kernel: Device mimpid: {:x}", sbi::get_mimpid());
 }
 
-fn test_sbi_ins_emulation() {
-    println!(">> Test-kernel: Testing SBI instruction emulation");
-    let time_start = riscv::register::time::read64();
-    println!("<< Test-kernel: Current time: {:x}", time_start);
-    let time_end = riscv::register::time::read64();
-    if time_end > time_start {
-        println!("<< Test-kernel: Time after operation: {:x}", time_end);
-    } else {
-        println!("!! Test-kernel: SBI test FAILED due to incorrect time counter");
-        sbi::shutdown()
-    }
+/// Hart ID of the current hart, kept in tp by the entry code
+pub fn hart_id() -> usize {
+    let hartid;
+    unsafe { core::arch::asm!("mv {}, tp", out(reg) hartid) };
+    hartid
 }
 
-pub extern "C" fn rust_trap_exception() {
-    let cause = scause::read().cause();
-    println!("<< Test-kernel: Value of scause: {:?}", cause);
-    if cause != Trap::Exception(Exception::IllegalInstruction) {
-        println!("!! Test-kernel: Wrong cause associated to illegal instruction");
-        sbi::shutdown()
+pub extern "C" fn rust_trap_handler() {
+    let cause = scause::read();
+    match cause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => conformance::time::on_timer(hart_id()),
+        Trap::Interrupt(Interrupt::SupervisorSoft) => conformance::ipi::on_ipi(hart_id()),
+        Trap::Interrupt(interrupt) => {
+            println!("!! Test-kernel: Unexpected interrupt: {:?}", interrupt)
+        }
+        Trap::Exception(exception) => {
+            println!("<< Test-kernel: Value of scause: {:?}", exception);
+            TRAP_CAUSE.store(cause.bits(), Ordering::Release);
+            // tests only trigger 4-byte instructions, skip it
+            sepc::write(sepc::read().wrapping_add(4));
+        }
     }
-    println!("<< Test-kernel: Illegal exception delegate success");
-    sepc::write(sepc::read().wrapping_add(4));
 }
 
 use core::panic::PanicInfo;
@@ -158,6 +97,8 @@ use core::panic::PanicInfo;
 fn panic(info: &PanicInfo) -> ! {
     println!("!! Test-kernel: {}", info);
     println!("!! Test-kernel: SBI test FAILED due to panic");
+    // no failed= field, `cargo xtask test` reports it as a failure
+    println!("test-kernel-result: panic");
     sbi::reset(sbi::RESET_TYPE_SHUTDOWN, sbi::RESET_REASON_SYSTEM_FAILURE);
     loop {}
 }
@@ -166,13 +107,14 @@ const BOOT_STACK_SIZE: usize = 0x10000 * 5;
 
 static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text.entry"]
 #[export_name = "_start"]
 unsafe extern "C" fn entry() -> ! {
-    core::arch::asm!("
-    # 1. set sp
+    core::arch::naked_asm!("
+    # 1. set sp and tp
     # sp = bootstack + (hartid + 1) * 0x10000
+    mv      tp, a0
     add     t0, a0, 1
     slli    t0, t0, 14
 1:  auipc   sp, %pcrel_hi({boot_stack})
@@ -185,7 +127,27 @@ unsafe extern "C" fn entry() -> ! {
     ", 
     boot_stack = sym BOOT_STACK,
     rust_main = sym rust_main,
-    options(noreturn))
+    )
+}
+
+/// Entry of harts started by HSM hart_start, a0 = hartid, a1 = opaque
+#[unsafe(naked)]
+pub unsafe extern "C" fn hsm_entry() -> ! {
+    core::arch::naked_asm!("
+    # sp = bootstack + (hartid + 1) * 0x4000, tp = hartid
+    mv      tp, a0
+    add     t0, a0, 1
+    slli    t0, t0, 14
+1:  auipc   sp, %pcrel_hi({boot_stack})
+    addi    sp, sp, %pcrel_lo(1b)
+    add     sp, sp, t0
+1:  auipc   t0, %pcrel_hi({rust_hsm_main})
+    addi    t0, t0, %pcrel_lo(1b)
+    jr      t0
+    ",
+    boot_stack = sym BOOT_STACK,
+    rust_hsm_main = sym conformance::hsm::rust_hsm_main,
+    )
 }
 
 #[cfg(target_pointer_width = "128")]
@@ -227,10 +189,10 @@ macro_rules! define_store_load {
     };
 }
 
-#[naked]
+#[unsafe(naked)]
 #[link_section = ".text"]
 unsafe extern "C" fn start_trap() {
-    core::arch::asm!(define_store_load!(), "
+    core::arch::naked_asm!(define_store_load!(), "
     .p2align 2
     addi    sp, sp, -16 * {REGBYTES}
     STORE   ra, 0
@@ -250,7 +212,7 @@ unsafe extern "C" fn start_trap() {
     STORE   a6, 14
     STORE   a7, 15
     mv      a0, sp
-    call    {rust_trap_exception}
+    call    {rust_trap_handler}
     LOAD    ra, 0
     LOAD    t0, 1
     LOAD    t1, 2
@@ -271,6 +233,6 @@ unsafe extern "C" fn start_trap() {
     sret
     ",
     REGBYTES = const core::mem::size_of::<usize>(),
-    rust_trap_exception = sym rust_trap_exception,
-    options(noreturn))
+    rust_trap_handler = sym rust_trap_handler,
+    )
 }
@@ -1,4 +1,5 @@
 use buddy_system_allocator::LockedHeap;
+use core::ptr::addr_of;
 
 const HEAP_SIZE: usize = 64 * 1024; // 64KiB
 #[link_section = ".bss.uninit"]
@@ -7,5 +8,5 @@ static mut HEAP_SPACE: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 static HEAP: LockedHeap<32> = LockedHeap::empty();
 
 pub fn init_heap() {
-    unsafe { HEAP.lock().init(HEAP_SPACE.as_ptr() as usize, HEAP_SIZE) }
+    unsafe { HEAP.lock().init(addr_of!(HEAP_SPACE) as usize, HEAP_SIZE) }
 }
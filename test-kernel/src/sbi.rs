@@ -8,6 +8,10 @@ pub const EXTENSION_IPI: usize = 0x735049;
 pub const EXTENSION_RFENCE: usize = 0x52464E43;
 pub const EXTENSION_HSM: usize = 0x48534D;
 pub const EXTENSION_SRST: usize = 0x53525354;
+pub const EXTENSION_PMU: usize = 0x504D55;
+pub const EXTENSION_DBCN: usize = 0x4442434E;
+/// SiFive vendor extension of RustSBI on HiFive Unmatched, 0x09000000 plus the SiFive mvendorid
+pub const EXTENSION_SIFIVE: usize = 0x0900_0489;
 
 const FUNCTION_BASE_GET_SPEC_VERSION: usize = 0x0;
 const FUNCTION_BASE_GET_SBI_IMPL_ID: usize = 0x1;
@@ -18,6 +22,7 @@ const FUNCTION_BASE_GET_MARCHID: usize = 0x5;
 const FUNCTION_BASE_GET_MIMPID: usize = 0x6;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct SbiRet {
     /// Error number
     pub error: usize,
@@ -25,15 +30,31 @@ pub struct SbiRet {
     pub value: usize,
 }
 
-const SBI_SUCCESS: usize = 0;
-const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
-const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
-const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
-const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
-const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
-const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
-const SBI_ERR_ALREADY_STARTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-7));
-const SBI_ERR_ALREADY_STOPPED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-8));
+pub const SBI_SUCCESS: usize = 0;
+pub const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
+pub const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
+pub const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
+pub const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
+pub const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
+pub const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
+pub const SBI_ERR_ALREADY_STARTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-7));
+pub const SBI_ERR_ALREADY_STOPPED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-8));
+
+/// Name of an SBI error code as written in the specification
+pub fn error_name(error: usize) -> &'static str {
+    match error {
+        SBI_SUCCESS => "SBI_SUCCESS",
+        SBI_ERR_FAILED => "SBI_ERR_FAILED",
+        SBI_ERR_NOT_SUPPORTED => "SBI_ERR_NOT_SUPPORTED",
+        SBI_ERR_INVALID_PARAM => "SBI_ERR_INVALID_PARAM",
+        SBI_ERR_DENIED => "SBI_ERR_DENIED",
+        SBI_ERR_INVALID_ADDRESS => "SBI_ERR_INVALID_ADDRESS",
+        SBI_ERR_ALREADY_AVAILABLE => "SBI_ERR_ALREADY_AVAILABLE",
+        SBI_ERR_ALREADY_STARTED => "SBI_ERR_ALREADY_STARTED",
+        SBI_ERR_ALREADY_STOPPED => "SBI_ERR_ALREADY_STOPPED",
+        _ => "<unknown SBI error>",
+    }
+}
 
 impl fmt::Debug for SbiRet {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -53,38 +74,44 @@ impl fmt::Debug for SbiRet {
 }
 
 #[inline]
-pub fn get_spec_version() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SPEC_VERSION).value
+pub fn get_spec_version() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SPEC_VERSION)
 }
 
 #[inline]
-pub fn get_sbi_impl_id() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SBI_IMPL_ID).value
+pub fn get_sbi_impl_id() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SBI_IMPL_ID)
 }
 
 #[inline]
-pub fn get_sbi_impl_version() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SBI_IMPL_VERSION).value
+pub fn get_sbi_impl_version() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_SBI_IMPL_VERSION)
 }
 
 #[inline]
-pub fn probe_extension(extension_id: usize) -> usize {
-    sbi_call_1(EXTENSION_BASE, FUNCTION_BASE_PROBE_EXTENSION, extension_id).value
+pub fn probe_extension(extension_id: usize) -> SbiRet {
+    sbi_call_1(EXTENSION_BASE, FUNCTION_BASE_PROBE_EXTENSION, extension_id)
+}
+
+/// Whether the extension is advertised by the SBI implementation
+pub fn has_extension(extension_id: usize) -> bool {
+    let ret = probe_extension(extension_id);
+    ret.error == SBI_SUCCESS && ret.value != 0
 }
 
 #[inline]
-pub fn get_mvendorid() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MVENDORID).value
+pub fn get_mvendorid() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MVENDORID)
 }
 
 #[inline]
-pub fn get_marchid() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MARCHID).value
+pub fn get_marchid() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MARCHID)
 }
 
 #[inline]
-pub fn get_mimpid() -> usize {
-    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MIMPID).value
+pub fn get_mimpid() -> SbiRet {
+    sbi_call_0(EXTENSION_BASE, FUNCTION_BASE_GET_MIMPID)
 }
 
 const FUNCTION_SYSTEM_RESET: usize = 0x0;
@@ -105,14 +132,13 @@ pub fn reset(reset_type: usize, reset_reason: usize) -> SbiRet {
     )
 }
 
+/// Shut down the machine; falls back to the legacy call if system reset is not available
 pub fn shutdown() -> ! {
-    sbi_call_2(
-        EXTENSION_SRST,
-        FUNCTION_SYSTEM_RESET,
-        RESET_TYPE_SHUTDOWN,
-        RESET_REASON_NO_REASON,
-    );
-    unreachable!()
+    reset(RESET_TYPE_SHUTDOWN, RESET_REASON_NO_REASON);
+    legacy_shutdown();
+    loop {
+        unsafe { riscv::asm::wfi() }
+    }
 }
 
 #[inline(always)]
@@ -137,26 +163,65 @@ fn sbi_call_legacy(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize
     ret
 }
 
-const SBI_SET_TIMER: usize = 0;
-const SBI_CONSOLE_PUTCHAR: usize = 1;
-const SBI_CONSOLE_GETCHAR: usize = 2;
-const SBI_CLEAR_IPI: usize = 3;
-const SBI_SEND_IPI: usize = 4;
-const SBI_REMOTE_FENCE_I: usize = 5;
-const SBI_REMOTE_SFENCE_VMA: usize = 6;
-const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
-const SBI_SHUTDOWN: usize = 8;
-
-pub fn console_putchar(c: usize) {
-    sbi_call_legacy(SBI_CONSOLE_PUTCHAR, c, 0, 0);
+pub const SBI_SET_TIMER: usize = 0;
+pub const SBI_CONSOLE_PUTCHAR: usize = 1;
+pub const SBI_CONSOLE_GETCHAR: usize = 2;
+pub const SBI_CLEAR_IPI: usize = 3;
+pub const SBI_SEND_IPI: usize = 4;
+pub const SBI_REMOTE_FENCE_I: usize = 5;
+pub const SBI_REMOTE_SFENCE_VMA: usize = 6;
+pub const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
+pub const SBI_SHUTDOWN: usize = 8;
+
+pub fn console_putchar(c: usize) -> usize {
+    sbi_call_legacy(SBI_CONSOLE_PUTCHAR, c, 0, 0)
 }
 
 pub fn console_getchar() -> usize {
     sbi_call_legacy(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
-pub fn set_timer(time: usize) {
-    sbi_call_legacy(SBI_SET_TIMER, time, 0, 0);
+pub fn legacy_set_timer(time: usize) -> usize {
+    sbi_call_legacy(SBI_SET_TIMER, time, 0, 0)
+}
+
+pub fn legacy_clear_ipi() -> usize {
+    sbi_call_legacy(SBI_CLEAR_IPI, 0, 0, 0)
+}
+
+/// Legacy IPI calls take the virtual address of a hart mask, not the mask itself
+pub fn legacy_send_ipi(hart_mask: *const usize) -> usize {
+    sbi_call_legacy(SBI_SEND_IPI, hart_mask as usize, 0, 0)
+}
+
+pub fn legacy_remote_fence_i(hart_mask: *const usize) -> usize {
+    sbi_call_legacy(SBI_REMOTE_FENCE_I, hart_mask as usize, 0, 0)
+}
+
+pub fn legacy_shutdown() {
+    sbi_call_legacy(SBI_SHUTDOWN, 0, 0, 0);
+}
+
+pub const LEGACY_EXTENSIONS: [(usize, &str); 9] = [
+    (SBI_SET_TIMER, "set_timer"),
+    (SBI_CONSOLE_PUTCHAR, "console_putchar"),
+    (SBI_CONSOLE_GETCHAR, "console_getchar"),
+    (SBI_CLEAR_IPI, "clear_ipi"),
+    (SBI_SEND_IPI, "send_ipi"),
+    (SBI_REMOTE_FENCE_I, "remote_fence_i"),
+    (SBI_REMOTE_SFENCE_VMA, "remote_sfence_vma"),
+    (SBI_REMOTE_SFENCE_VMA_ASID, "remote_sfence_vma_asid"),
+    (SBI_SHUTDOWN, "shutdown"),
+];
+
+const FUNCTION_TIMER_SET_TIMER: usize = 0x0;
+
+pub fn set_timer(stime_value: u64) -> SbiRet {
+    sbi_call_1(
+        EXTENSION_TIMER,
+        FUNCTION_TIMER_SET_TIMER,
+        stime_value as usize,
+    )
 }
 
 const FUNCTION_IPI_SEND_IPI: usize = 0x0;
@@ -170,6 +235,49 @@ pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
     )
 }
 
+const FUNCTION_RFENCE_REMOTE_FENCE_I: usize = 0x0;
+const FUNCTION_RFENCE_REMOTE_SFENCE_VMA: usize = 0x1;
+const FUNCTION_RFENCE_REMOTE_SFENCE_VMA_ASID: usize = 0x2;
+
+pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    sbi_call_2(
+        EXTENSION_RFENCE,
+        FUNCTION_RFENCE_REMOTE_FENCE_I,
+        hart_mask,
+        hart_mask_base,
+    )
+}
+
+pub fn remote_sfence_vma(
+    hart_mask: usize,
+    hart_mask_base: usize,
+    start_addr: usize,
+    size: usize,
+) -> SbiRet {
+    sbi_call_4(
+        EXTENSION_RFENCE,
+        FUNCTION_RFENCE_REMOTE_SFENCE_VMA,
+        hart_mask,
+        hart_mask_base,
+        start_addr,
+        size,
+    )
+}
+
+pub fn remote_sfence_vma_asid(
+    hart_mask: usize,
+    hart_mask_base: usize,
+    start_addr: usize,
+    size: usize,
+    asid: usize,
+) -> SbiRet {
+    sbi_call(
+        EXTENSION_RFENCE,
+        FUNCTION_RFENCE_REMOTE_SFENCE_VMA_ASID,
+        [hart_mask, hart_mask_base, start_addr, size, asid, 0],
+    )
+}
+
 const FUNCTION_HSM_HART_START: usize = 0x0;
 const FUNCTION_HSM_HART_STOP: usize = 0x1;
 const FUNCTION_HSM_HART_GET_STATUS: usize = 0x2;
@@ -185,8 +293,17 @@ pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> SbiRet {
     )
 }
 
-pub fn hart_stop(hartid: usize) -> SbiRet {
-    sbi_call_1(EXTENSION_HSM, FUNCTION_HSM_HART_STOP, hartid)
+pub const HART_STATE_STARTED: usize = 0;
+pub const HART_STATE_STOPPED: usize = 1;
+pub const HART_STATE_START_PENDING: usize = 2;
+pub const HART_STATE_STOP_PENDING: usize = 3;
+
+pub const SUSPEND_TYPE_RETENTIVE: u32 = 0x0000_0000;
+pub const SUSPEND_TYPE_NON_RETENTIVE: u32 = 0x8000_0000;
+
+/// Stops the calling hart; only returns on failure
+pub fn hart_stop() -> SbiRet {
+    sbi_call_0(EXTENSION_HSM, FUNCTION_HSM_HART_STOP)
 }
 
 pub fn hart_get_status(hartid: usize) -> SbiRet {
@@ -203,6 +320,106 @@ pub fn hart_suspend(suspend_type: u32, resume_addr: usize, opaque: usize) -> Sbi
     )
 }
 
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_STOP: usize = 0x4;
+
+pub fn pmu_num_counters() -> SbiRet {
+    sbi_call_0(EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS)
+}
+
+pub fn pmu_counter_get_info(counter_idx: usize) -> SbiRet {
+    sbi_call_1(EXTENSION_PMU, FUNCTION_PMU_COUNTER_GET_INFO, counter_idx)
+}
+
+pub fn pmu_counter_stop(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    stop_flags: usize,
+) -> SbiRet {
+    sbi_call_3(
+        EXTENSION_PMU,
+        FUNCTION_PMU_COUNTER_STOP,
+        counter_idx_base,
+        counter_idx_mask,
+        stop_flags,
+    )
+}
+
+const FUNCTION_DBCN_CONSOLE_WRITE: usize = 0x0;
+const FUNCTION_DBCN_CONSOLE_READ: usize = 0x1;
+const FUNCTION_DBCN_CONSOLE_WRITE_BYTE: usize = 0x2;
+
+/// The buffer is passed by physical address; test-kernel runs without paging, so pa == va
+pub fn console_write(bytes: &[u8]) -> SbiRet {
+    sbi_call_3(
+        EXTENSION_DBCN,
+        FUNCTION_DBCN_CONSOLE_WRITE,
+        bytes.len(),
+        bytes.as_ptr() as usize,
+        0,
+    )
+}
+
+pub fn console_read(buf: &mut [u8]) -> SbiRet {
+    sbi_call_3(
+        EXTENSION_DBCN,
+        FUNCTION_DBCN_CONSOLE_READ,
+        buf.len(),
+        buf.as_mut_ptr() as usize,
+        0,
+    )
+}
+
+pub fn console_write_byte(byte: u8) -> SbiRet {
+    sbi_call_1(
+        EXTENSION_DBCN,
+        FUNCTION_DBCN_CONSOLE_WRITE_BYTE,
+        byte as usize,
+    )
+}
+
+const FUNCTION_SIFIVE_L2_CONFIG: usize = 0x0;
+const FUNCTION_SIFIVE_L2_ECC_COUNT: usize = 0x1;
+const FUNCTION_SIFIVE_L2_ECC_LAST_ADDRESS: usize = 0x2;
+const FUNCTION_SIFIVE_L2_FLUSH_RANGE: usize = 0x3;
+
+pub fn sifive_l2_config() -> SbiRet {
+    sbi_call_0(EXTENSION_SIFIVE, FUNCTION_SIFIVE_L2_CONFIG)
+}
+
+pub fn sifive_l2_ecc_count(kind: usize) -> SbiRet {
+    sbi_call_1(EXTENSION_SIFIVE, FUNCTION_SIFIVE_L2_ECC_COUNT, kind)
+}
+
+pub fn sifive_l2_ecc_last_address(kind: usize) -> SbiRet {
+    sbi_call_1(EXTENSION_SIFIVE, FUNCTION_SIFIVE_L2_ECC_LAST_ADDRESS, kind)
+}
+
+pub fn sifive_l2_flush_range(address: usize, size: usize) -> SbiRet {
+    sbi_call_2(
+        EXTENSION_SIFIVE,
+        FUNCTION_SIFIVE_L2_FLUSH_RANGE,
+        address,
+        size,
+    )
+}
+
+/// Raw SBI call with all six argument registers, for arbitrary and malformed calls
+#[inline(always)]
+pub fn sbi_call(extension: usize, function: usize, args: [usize; 6]) -> SbiRet {
+    let (error, value);
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => error, inlateout("a1") args[1] => value,
+            in("a2") args[2], in("a3") args[3], in("a4") args[4], in("a5") args[5],
+            in("a6") function, in("a7") extension,
+        )
+    };
+    SbiRet { error, value }
+}
+
 #[inline(always)]
 fn sbi_call_0(extension: usize, function: usize) -> SbiRet {
     let (error, value);
@@ -257,3 +474,24 @@ fn sbi_call_3(extension: usize, function: usize, arg0: usize, arg1: usize, arg2:
     };
     SbiRet { error, value }
 }
+
+#[inline(always)]
+fn sbi_call_4(
+    extension: usize,
+    function: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+) -> SbiRet {
+    let (error, value);
+    unsafe {
+        asm!(
+            "ecall",
+            in("a0") arg0, in("a1") arg1, in("a2") arg2, in("a3") arg3,
+            in("a6") function, in("a7") extension,
+            lateout("a0") error, lateout("a1") value,
+        )
+    };
+    SbiRet { error, value }
+}
@@ -0,0 +1,115 @@
+//! Bookkeeping for conformance tests
+//!
+//! Every check prints one line: `<< [PASS]`, `!! [FAIL]` or `<< [SKIP]` followed by
+//! `group.name` and details. `finish` prints the summary and one machine-readable line
+//! in the form `test-kernel-result: passed=N failed=N skipped=N`, which `cargo xtask test`
+//! reads to decide the verdict.
+use crate::println;
+use crate::sbi::{self, SbiRet};
+use core::fmt;
+
+pub struct Suite {
+    group: &'static str,
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Suite {
+    pub const fn new() -> Self {
+        Suite {
+            group: "",
+            passed: 0,
+            failed: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Start a group of tests, usually one SBI extension
+    pub fn group(&mut self, group: &'static str) {
+        self.group = group;
+        println!(">> Test-kernel: Testing {}", group);
+    }
+
+    /// Record the result of a check; returns `ok` so callers can stop on failures
+    pub fn check(&mut self, name: &str, ok: bool, detail: fmt::Arguments) -> bool {
+        if ok {
+            self.passed += 1;
+            println!("<< [PASS] {}.{}: {}", self.group, name, detail);
+        } else {
+            self.failed += 1;
+            println!("!! [FAIL] {}.{}: {}", self.group, name, detail);
+        }
+        ok
+    }
+
+    pub fn skip(&mut self, name: &str, reason: &str) {
+        self.skipped += 1;
+        println!("<< [SKIP] {}.{}: {}", self.group, name, reason);
+    }
+
+    /// Check that an SBI call returned the expected error code
+    pub fn expect(&mut self, name: &str, ret: SbiRet, error: usize) -> bool {
+        self.check(
+            name,
+            ret.error == error,
+            format_args!(
+                "expected {}, got {} (value {:#x})",
+                sbi::error_name(error),
+                sbi::error_name(ret.error),
+                ret.value
+            ),
+        )
+    }
+
+    /// Check that an SBI call succeeded and passes its value to `f` for further checking
+    pub fn expect_value(&mut self, name: &str, ret: SbiRet, f: impl FnOnce(usize) -> bool) -> bool {
+        if ret.error != sbi::SBI_SUCCESS {
+            return self.expect(name, ret, sbi::SBI_SUCCESS);
+        }
+        self.check(name, f(ret.value), format_args!("value {:#x}", ret.value))
+    }
+
+    /// Skip the whole group if the extension is not advertised
+    pub fn require_extension(&mut self, extension_id: usize) -> bool {
+        if sbi::has_extension(extension_id) {
+            true
+        } else {
+            self.skip("probe", "extension not available");
+            false
+        }
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Print the summary and the machine-readable result line; returns whether all checks passed
+    pub fn finish(&self) -> bool {
+        println!(
+            "<< Test-kernel: {} passed, {} failed, {} skipped",
+            self.passed, self.failed, self.skipped
+        );
+        println!(
+            "test-kernel-result: passed={} failed={} skipped={}",
+            self.passed, self.failed, self.skipped
+        );
+        self.failed == 0
+    }
+}
+
+/// Clear the supervisor software interrupt pending bit
+pub fn clear_ssip() {
+    unsafe { core::arch::asm!("csrc sip, {}", in(reg) 1 << 1) };
+}
+
+/// Spin until `f` holds or `limit` iterations pass; returns whether `f` held
+pub fn wait_until(limit: usize, mut f: impl FnMut() -> bool) -> bool {
+    for _ in 0..limit {
+        if f() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    f()
+}
@@ -0,0 +1,376 @@
+//! Zba和Zbb位操作指令的模拟
+//!
+//! U74没有实现位操作扩展，为较新的RVA配置编译的程序会遇到非法指令。
+//! 打开`bitmanip`特性后，这些指令在M层按规范计算结果并写回rd，速度很慢，但结果正确。
+use crate::decode::{self, Instruction};
+use crate::Hart;
+
+const XLEN: u32 = usize::BITS;
+
+const OPCODE_OP_IMM: u32 = 0b001_0011;
+const OPCODE_OP: u32 = 0b011_0011;
+#[cfg(target_pointer_width = "64")]
+const OPCODE_OP_IMM_32: u32 = 0b001_1011;
+#[cfg(target_pointer_width = "64")]
+const OPCODE_OP_32: u32 = 0b011_1011;
+
+// rev8的立即数字段和XLEN有关
+#[cfg(target_pointer_width = "64")]
+const IMM_REV8: u32 = 0b0110_1011_1000;
+#[cfg(target_pointer_width = "32")]
+const IMM_REV8: u32 = 0b0110_1001_1000;
+const IMM_ORC_B: u32 = 0b0010_1000_0111;
+
+/// 模拟Zba或Zbb指令，返回是否完成了模拟
+pub fn emulate_bitmanip(hart: &mut impl Hart, ins: Instruction) -> bool {
+    let bits = match ins {
+        Instruction::Standard(bits) => bits,
+        _ => return false,
+    };
+    let rs1 = hart.x(decode::rs1(bits));
+    let rs2 = hart.x(decode::rs2(bits));
+    match execute(bits, rs1, rs2) {
+        Some(value) => {
+            hart.set_x(decode::rd(bits), value);
+            decode::skip_instruction(hart, ins);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 计算指令的结果；不是Zba或Zbb指令时返回`None`
+///
+/// 立即数形式的指令不使用rs2，但rs2字段的值也会传进来，这里忽略它。
+pub fn execute(bits: u32, rs1: usize, rs2: usize) -> Option<usize> {
+    let funct3 = decode::funct3(bits);
+    let funct7 = decode::funct7(bits);
+    let imm = bits >> 20;
+    let shamt = (bits >> 20) & (XLEN - 1);
+    let value = match (decode::opcode(bits), funct7, funct3) {
+        // Zba
+        (OPCODE_OP, 0b001_0000, 0b010) => (rs1 << 1).wrapping_add(rs2), // sh1add
+        (OPCODE_OP, 0b001_0000, 0b100) => (rs1 << 2).wrapping_add(rs2), // sh2add
+        (OPCODE_OP, 0b001_0000, 0b110) => (rs1 << 3).wrapping_add(rs2), // sh3add
+        // Zbb，寄存器形式
+        (OPCODE_OP, 0b010_0000, 0b111) => rs1 & !rs2, // andn
+        (OPCODE_OP, 0b010_0000, 0b110) => rs1 | !rs2, // orn
+        (OPCODE_OP, 0b010_0000, 0b100) => !(rs1 ^ rs2), // xnor
+        (OPCODE_OP, 0b000_0101, 0b100) => (rs1 as isize).min(rs2 as isize) as usize, // min
+        (OPCODE_OP, 0b000_0101, 0b101) => rs1.min(rs2), // minu
+        (OPCODE_OP, 0b000_0101, 0b110) => (rs1 as isize).max(rs2 as isize) as usize, // max
+        (OPCODE_OP, 0b000_0101, 0b111) => rs1.max(rs2), // maxu
+        (OPCODE_OP, 0b011_0000, 0b001) => rs1.rotate_left(rs2 as u32 & (XLEN - 1)), // rol
+        (OPCODE_OP, 0b011_0000, 0b101) => rs1.rotate_right(rs2 as u32 & (XLEN - 1)), // ror
+        #[cfg(target_pointer_width = "32")]
+        (OPCODE_OP, 0b000_0100, 0b100) if decode::rs2(bits) == 0 => rs1 & 0xFFFF, // zext.h
+        // Zbb，立即数形式
+        (OPCODE_OP_IMM, _, 0b001) => match imm {
+            0b0110_0000_0000 => rs1.leading_zeros() as usize, // clz
+            0b0110_0000_0001 => rs1.trailing_zeros() as usize, // ctz
+            0b0110_0000_0010 => rs1.count_ones() as usize,    // cpop
+            0b0110_0000_0100 => rs1 as i8 as isize as usize,  // sext.b
+            0b0110_0000_0101 => rs1 as i16 as isize as usize, // sext.h
+            _ => return None,
+        },
+        (OPCODE_OP_IMM, _, 0b101) if imm == IMM_REV8 => rs1.swap_bytes(), // rev8
+        (OPCODE_OP_IMM, _, 0b101) if imm == IMM_ORC_B => orc_b(rs1),      // orc.b
+        // rori的shamt在RV64上有6位，占用了funct7的最低位；RV32上shamt只有5位，第5位必须为0
+        (OPCODE_OP_IMM, _, 0b101) if imm >> 6 == 0b01_1000 && imm & XLEN == 0 => {
+            rs1.rotate_right(shamt)
+        }
+        #[cfg(target_pointer_width = "64")]
+        (OPCODE_OP_32, funct7, funct3) => return execute_op_32(funct7, funct3, bits, rs1, rs2),
+        #[cfg(target_pointer_width = "64")]
+        (OPCODE_OP_IMM_32, _, funct3) => return execute_op_imm_32(funct3, imm, rs1),
+        _ => return None,
+    };
+    Some(value)
+}
+
+// 每个非零字节变成0xFF，零字节保持为0
+fn orc_b(value: usize) -> usize {
+    let mut ans = 0;
+    for i in 0..core::mem::size_of::<usize>() {
+        if (value >> (i * 8)) & 0xFF != 0 {
+            ans |= 0xFF << (i * 8);
+        }
+    }
+    ans
+}
+
+// 32位结果符号扩展到64位
+#[cfg(target_pointer_width = "64")]
+#[inline]
+fn sext_w(value: u32) -> usize {
+    value as i32 as isize as usize
+}
+
+// RV64上的OP-32指令：Zba的*.uw和Zbb的字操作
+#[cfg(target_pointer_width = "64")]
+fn execute_op_32(funct7: u32, funct3: u32, bits: u32, rs1: usize, rs2: usize) -> Option<usize> {
+    let rs1_uw = rs1 & 0xFFFF_FFFF;
+    let value = match (funct7, funct3) {
+        (0b000_0100, 0b000) => rs1_uw.wrapping_add(rs2), // add.uw
+        (0b001_0000, 0b010) => (rs1_uw << 1).wrapping_add(rs2), // sh1add.uw
+        (0b001_0000, 0b100) => (rs1_uw << 2).wrapping_add(rs2), // sh2add.uw
+        (0b001_0000, 0b110) => (rs1_uw << 3).wrapping_add(rs2), // sh3add.uw
+        (0b000_0100, 0b100) if decode::rs2(bits) == 0 => rs1 & 0xFFFF, // zext.h
+        (0b011_0000, 0b001) => sext_w((rs1 as u32).rotate_left(rs2 as u32 & 31)), // rolw
+        (0b011_0000, 0b101) => sext_w((rs1 as u32).rotate_right(rs2 as u32 & 31)), // rorw
+        _ => return None,
+    };
+    Some(value)
+}
+
+// RV64上的OP-IMM-32指令：slli.uw和Zbb的字操作
+#[cfg(target_pointer_width = "64")]
+fn execute_op_imm_32(funct3: u32, imm: u32, rs1: usize) -> Option<usize> {
+    let value = match (funct3, imm) {
+        // slli.uw
+        (0b001, imm) if imm >> 6 == 0b00_0010 => (rs1 & 0xFFFF_FFFF) << (imm & 0b11_1111),
+        (0b001, 0b0110_0000_0000) => (rs1 as u32).leading_zeros() as usize, // clzw
+        (0b001, 0b0110_0000_0001) => (rs1 as u32).trailing_zeros() as usize, // ctzw
+        (0b001, 0b0110_0000_0010) => (rs1 as u32).count_ones() as usize,    // cpopw
+        // roriw
+        (0b101, imm) if imm >> 5 == 0b011_0000 => sext_w((rs1 as u32).rotate_right(imm & 31)),
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emulate_bitmanip, execute};
+    use crate::decode::Instruction;
+    use crate::mock::MockHart;
+    use crate::Hart;
+
+    // R-type encoding with rd = a0, rs1 = a1, rs2 = a2
+    fn r(opcode: u32, funct7: u32, funct3: u32) -> u32 {
+        funct7 << 25 | 12 << 20 | 11 << 15 | funct3 << 12 | 10 << 7 | opcode
+    }
+
+    // I-type encoding with rd = a0, rs1 = a1
+    fn i(opcode: u32, imm: u32, funct3: u32) -> u32 {
+        imm << 20 | 11 << 15 | funct3 << 12 | 10 << 7 | opcode
+    }
+
+    const OP: u32 = 0b011_0011;
+    const OP_IMM: u32 = 0b001_0011;
+    const OP_32: u32 = 0b011_1011;
+    const OP_IMM_32: u32 = 0b001_1011;
+
+    #[test]
+    fn zba_shift_and_add() {
+        assert_eq!(execute(r(OP, 0b0010000, 0b010), 3, 100), Some(106)); // sh1add
+        assert_eq!(execute(r(OP, 0b0010000, 0b100), 3, 100), Some(112)); // sh2add
+        assert_eq!(execute(r(OP, 0b0010000, 0b110), 3, 100), Some(124)); // sh3add
+        assert_eq!(
+            execute(r(OP, 0b0010000, 0b110), usize::MAX, 0),
+            Some(usize::MAX << 3)
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn zba_unsigned_word() {
+        let rs1 = 0xFFFF_FFFF_8000_0001;
+        assert_eq!(
+            execute(r(OP_32, 0b0000100, 0b000), rs1, 1), // add.uw
+            Some(0x8000_0002)
+        );
+        assert_eq!(
+            execute(r(OP_32, 0b0010000, 0b010), rs1, 0), // sh1add.uw
+            Some(0x1_0000_0002)
+        );
+        assert_eq!(
+            execute(r(OP_32, 0b0010000, 0b100), rs1, 0), // sh2add.uw
+            Some(0x2_0000_0004)
+        );
+        assert_eq!(
+            execute(r(OP_32, 0b0010000, 0b110), rs1, 0), // sh3add.uw
+            Some(0x4_0000_0008)
+        );
+        // slli.uw a0, a1, 4
+        assert_eq!(
+            execute(i(OP_IMM_32, 0b000010 << 6 | 4, 0b001), rs1, 0),
+            Some(0x8_0000_0010)
+        );
+        // slli.uw a0, a1, 32
+        assert_eq!(
+            execute(i(OP_IMM_32, 0b000010 << 6 | 32, 0b001), rs1, 0),
+            Some(0x8000_0001 << 32)
+        );
+    }
+
+    #[test]
+    fn zbb_logic_with_negate() {
+        let (a, b) = (0b1100, 0b1010);
+        assert_eq!(execute(r(OP, 0b0100000, 0b111), a, b), Some(0b0100)); // andn
+        assert_eq!(execute(r(OP, 0b0100000, 0b110), a, b), Some(!0b0010)); // orn
+        assert_eq!(execute(r(OP, 0b0100000, 0b100), a, b), Some(!0b0110)); // xnor
+    }
+
+    #[test]
+    fn zbb_min_max() {
+        let minus_one = usize::MAX;
+        assert_eq!(
+            execute(r(OP, 0b0000101, 0b100), minus_one, 1),
+            Some(minus_one)
+        ); // min
+        assert_eq!(execute(r(OP, 0b0000101, 0b101), minus_one, 1), Some(1)); // minu
+        assert_eq!(execute(r(OP, 0b0000101, 0b110), minus_one, 1), Some(1)); // max
+        assert_eq!(
+            execute(r(OP, 0b0000101, 0b111), minus_one, 1),
+            Some(minus_one)
+        ); // maxu
+    }
+
+    #[test]
+    fn zbb_count() {
+        assert_eq!(
+            execute(i(OP_IMM, 0x600, 0b001), 1, 0),
+            Some(usize::BITS as usize - 1)
+        ); // clz
+        assert_eq!(
+            execute(i(OP_IMM, 0x600, 0b001), 0, 0),
+            Some(usize::BITS as usize)
+        );
+        assert_eq!(execute(i(OP_IMM, 0x601, 0b001), 0b1000, 0), Some(3)); // ctz
+        assert_eq!(
+            execute(i(OP_IMM, 0x601, 0b001), 0, 0),
+            Some(usize::BITS as usize)
+        );
+        assert_eq!(execute(i(OP_IMM, 0x602, 0b001), 0xF0F0, 0), Some(8)); // cpop
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn zbb_count_word() {
+        let rs1 = 0xFFFF_FFFF_0000_0100;
+        assert_eq!(execute(i(OP_IMM_32, 0x600, 0b001), rs1, 0), Some(23)); // clzw
+        assert_eq!(execute(i(OP_IMM_32, 0x601, 0b001), rs1, 0), Some(8)); // ctzw
+        assert_eq!(execute(i(OP_IMM_32, 0x602, 0b001), rs1, 0), Some(1)); // cpopw
+        assert_eq!(execute(i(OP_IMM_32, 0x600, 0b001), 1 << 40, 0), Some(32));
+    }
+
+    #[test]
+    fn zbb_sign_and_zero_extend() {
+        assert_eq!(
+            execute(i(OP_IMM, 0x604, 0b001), 0x180, 0),
+            Some(usize::MAX - 0x7F)
+        ); // sext.b
+        assert_eq!(execute(i(OP_IMM, 0x604, 0b001), 0x17F, 0), Some(0x7F));
+        assert_eq!(
+            execute(i(OP_IMM, 0x605, 0b001), 0x1_8000, 0),
+            Some(usize::MAX - 0x7FFF)
+        ); // sext.h
+           // zext.h is encoded with rs2 = x0
+        let zext_h = r(OP_32, 0b0000100, 0b100) & !(0b11111 << 20);
+        #[cfg(target_pointer_width = "32")]
+        let zext_h = r(OP, 0b0000100, 0b100) & !(0b11111 << 20);
+        assert_eq!(execute(zext_h, 0x1_2345_FFFF, 0), Some(0xFFFF));
+    }
+
+    #[test]
+    fn zbb_rotate() {
+        let rs1 = 0x8000_0000_0000_0001_u64 as usize;
+        assert_eq!(
+            execute(r(OP, 0b0110000, 0b001), rs1, 1),
+            Some(rs1.rotate_left(1))
+        ); // rol
+        assert_eq!(
+            execute(r(OP, 0b0110000, 0b101), rs1, 1),
+            Some(rs1.rotate_right(1))
+        ); // ror
+           // only the low log2(XLEN) bits of rs2 are used
+        assert_eq!(
+            execute(r(OP, 0b0110000, 0b001), rs1, usize::BITS as usize + 1),
+            Some(rs1.rotate_left(1))
+        );
+        // rori a0, a1, 4
+        assert_eq!(
+            execute(i(OP_IMM, 0x600 | 4, 0b101), rs1, 0),
+            Some(rs1.rotate_right(4))
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn zbb_rotate_word() {
+        let rs1 = 0x1234_5678_8000_0001;
+        // results are sign extended from 32 bits
+        assert_eq!(execute(r(OP_32, 0b0110000, 0b001), rs1, 1), Some(3)); // rolw
+        assert_eq!(
+            execute(r(OP_32, 0b0110000, 0b101), rs1, 1), // rorw
+            Some(0xFFFF_FFFF_C000_0000)
+        );
+        assert_eq!(
+            execute(i(OP_IMM_32, 0x600 | 1, 0b101), rs1, 0), // roriw
+            Some(0xFFFF_FFFF_C000_0000)
+        );
+        // rori a0, a1, 33 uses the sixth shamt bit
+        assert_eq!(
+            execute(i(OP_IMM, 0x600 | 33, 0b101), rs1, 0),
+            Some(rs1.rotate_right(33))
+        );
+    }
+
+    #[test]
+    fn zbb_byte_operations() {
+        let rs1 = 0x0102_0304_0500_0700_u64 as usize;
+        // rev8 a0, a1
+        let rev8 = if cfg!(target_pointer_width = "64") {
+            0x6B8
+        } else {
+            0x698
+        };
+        assert_eq!(
+            execute(i(OP_IMM, rev8, 0b101), rs1, 0),
+            Some(rs1.swap_bytes())
+        );
+        // orc.b a0, a1
+        assert_eq!(
+            execute(i(OP_IMM, 0x287, 0b101), rs1, 0),
+            Some(0xFFFF_FFFF_FF00_FF00_u64 as usize)
+        );
+    }
+
+    #[test]
+    fn base_instructions_are_not_bitmanip() {
+        for bits in [
+            r(OP, 0b0000000, 0b000),    // add
+            r(OP, 0b0100000, 0b000),    // sub
+            r(OP, 0b0000001, 0b100),    // div
+            r(OP, 0b0100000, 0b101),    // sra
+            i(OP_IMM, 0x001, 0b001),    // slli a0, a1, 1
+            i(OP_IMM, 0x401, 0b101),    // srai a0, a1, 1
+            i(OP_IMM, 0x603, 0b001),    // reserved
+            r(OP_32, 0b0000000, 0b000), // addw
+            0x00000073,                 // ecall
+        ] {
+            assert_eq!(execute(bits, 1, 2), None, "{:#010x}", bits);
+        }
+    }
+
+    #[test]
+    fn emulation_writes_rd_and_skips_instruction() {
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.set_x(11, 0b1100);
+        hart.set_x(12, 0b1010);
+        // andn a0, a1, a2
+        let andn = Instruction::Standard(r(OP, 0b0100000, 0b111));
+        assert!(emulate_bitmanip(&mut hart, andn));
+        assert_eq!(hart.x(10), 0b0100);
+        assert_eq!(hart.mepc(), 0x8020_0004);
+        // add a0, a1, a2 is left alone
+        let add = Instruction::Standard(r(OP, 0, 0));
+        assert!(!emulate_bitmanip(&mut hart, add));
+        assert_eq!(hart.mepc(), 0x8020_0004);
+        assert!(!emulate_bitmanip(
+            &mut hart,
+            Instruction::Compressed(0x8082)
+        ));
+    }
+}
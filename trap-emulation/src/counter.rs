@@ -0,0 +1,259 @@
+//! 用户计数器CSR的读取模拟
+//!
+//! cycle、time、instret和hpmcounter3到hpmcounter31是只读的CSR。每个核有自己的策略：
+//! 一部分计数器写入mcounteren，由硬件直接开放给S层；另一部分在S层和U层读取时陷入M层，
+//! 在这里模拟，返回M层计数器的值。两者都不包括的计数器按非法指令转交给S层。
+use crate::decode::{self, Instruction};
+use crate::{Hart, PrivilegeMode};
+
+/// 一个核上各个计数器的开放方式，第i位对应第i个计数器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterPolicy {
+    /// 写入mcounteren，读取时不陷入M层的计数器
+    pub direct: u32,
+    /// 读取时陷入M层并在这里模拟的计数器
+    pub emulated: u32,
+}
+
+impl CounterPolicy {
+    pub const CYCLE: u32 = 1 << 0;
+    pub const TIME: u32 = 1 << 1;
+    pub const INSTRET: u32 = 1 << 2;
+    /// hpmcounter3到hpmcounter31
+    pub const HPM: u32 = !0b111;
+
+    /// 所有计数器都由M层模拟
+    pub const EMULATE_ALL: CounterPolicy = CounterPolicy {
+        direct: 0,
+        emulated: u32::MAX,
+    };
+
+    /// 应当写入mcounteren的值
+    #[inline]
+    pub fn mcounteren(&self) -> usize {
+        self.direct as usize
+    }
+}
+
+// 计数器CSR的编号
+const CSR_CYCLE: usize = 0xC00;
+const CSR_HPMCOUNTER31: usize = 0xC1F;
+// RV32上保存高32位的计数器CSR
+const CSR_CYCLEH: usize = 0xC80;
+const CSR_HPMCOUNTER31H: usize = 0xC9F;
+
+const OPCODE_SYSTEM: u32 = 0b111_0011;
+const FUNCT3_CSRRS: u32 = 0b010;
+const FUNCT3_CSRRC: u32 = 0b011;
+const FUNCT3_CSRRSI: u32 = 0b110;
+const FUNCT3_CSRRCI: u32 = 0b111;
+
+/// 模拟读取用户计数器的CSR指令，返回是否完成了模拟
+///
+/// 计数器是只读的：只有不写入CSR的csrrs、csrrc、csrrsi和csrrci（rs1或立即数为0）可以模拟，
+/// csrrw等写入形式本身就是非法指令。
+pub fn emulate_counter_read(hart: &mut impl Hart, ins: Instruction) -> bool {
+    let bits = match ins {
+        Instruction::Standard(bits) => bits,
+        _ => return false,
+    };
+    if decode::opcode(bits) != OPCODE_SYSTEM {
+        return false;
+    }
+    match decode::funct3(bits) {
+        FUNCT3_CSRRS | FUNCT3_CSRRC | FUNCT3_CSRRSI | FUNCT3_CSRRCI => {}
+        _ => return false,
+    }
+    // rs1和uimm在同一个字段
+    if decode::rs1(bits) != 0 {
+        return false;
+    }
+    let (index, high) = match decode::csr(bits) {
+        csr @ CSR_CYCLE..=CSR_HPMCOUNTER31 => (csr - CSR_CYCLE, false),
+        csr @ CSR_CYCLEH..=CSR_HPMCOUNTER31H if cfg!(target_pointer_width = "32") => {
+            (csr - CSR_CYCLEH, true)
+        }
+        _ => return false,
+    };
+    if hart.counter_policy().emulated & (1 << index) == 0 {
+        return false;
+    }
+    // U层能否读取计数器由S层的scounteren决定，不允许时由S层处理这个非法指令
+    if hart.previous_mode() == PrivilegeMode::User && hart.scounteren() & (1 << index) == 0 {
+        return false;
+    }
+    let value = if index == 1 {
+        hart.mtime()
+    } else {
+        hart.machine_counter(index)
+    };
+    let value = if high { value >> 32 } else { value };
+    hart.set_x(decode::rd(bits), value as usize);
+    decode::skip_instruction(hart, ins);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emulate_counter_read, CounterPolicy};
+    use crate::decode::Instruction;
+    use crate::mock::MockHart;
+    use crate::{Hart, PrivilegeMode};
+
+    const RDCYCLE_A0: u32 = 0xC0002573;
+    const RDTIME_A0: u32 = 0xC0102573;
+    const RDINSTRET_A0: u32 = 0xC0202573;
+    // csrr a0, hpmcounter5
+    const RDHPM5_A0: u32 = 0xC0502573;
+
+    fn hart() -> MockHart {
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.mtime = 1000;
+        for (i, counter) in hart.counters.iter_mut().enumerate() {
+            *counter = 0x1_0000_0000 * i as u64 + 7;
+        }
+        hart
+    }
+
+    fn emulate(hart: &mut MockHart, bits: u32) -> bool {
+        emulate_counter_read(hart, Instruction::Standard(bits))
+    }
+
+    #[test]
+    fn rdtime_returns_mtime() {
+        let mut hart = hart();
+        assert!(emulate(&mut hart, RDTIME_A0));
+        assert_eq!(hart.x(10), 1000);
+        assert_eq!(hart.mepc(), 0x8020_0004);
+    }
+
+    #[test]
+    fn rdtime_every_destination_register() {
+        for rd in 1..32 {
+            let mut hart = hart();
+            hart.mtime = 42;
+            assert!(emulate(&mut hart, 0xC0102073 | (rd << 7)));
+            for i in 1..32 {
+                let expected = if i == rd as usize { 42 } else { 0 };
+                assert_eq!(hart.x(i), expected, "rd = x{}, checking x{}", rd, i);
+            }
+        }
+    }
+
+    #[test]
+    fn counter_read_to_x0_is_discarded() {
+        let mut hart = hart();
+        assert!(emulate(&mut hart, 0xC0102073));
+        assert_eq!(hart.x(0), 0);
+        assert_eq!(hart.mepc(), 0x8020_0004);
+    }
+
+    #[test]
+    fn cycle_instret_and_hpmcounter_return_machine_counters() {
+        for (bits, index) in [(RDCYCLE_A0, 0), (RDINSTRET_A0, 2), (RDHPM5_A0, 5)] {
+            let mut hart = hart();
+            assert!(emulate(&mut hart, bits), "{:#x}", bits);
+            assert_eq!(hart.x(10) as u64, hart.counters[index]);
+            assert_eq!(hart.mepc(), 0x8020_0004);
+        }
+        // csrr a0, hpmcounter31
+        let mut hart = hart();
+        assert!(emulate(&mut hart, 0xC1F02573));
+        assert_eq!(hart.x(10) as u64, hart.counters[31]);
+    }
+
+    #[test]
+    fn read_forms_are_emulated() {
+        for bits in [
+            0xC0002573, // csrrs a0, cycle, x0
+            0xC0003573, // csrrc a0, cycle, x0
+            0xC0006573, // csrrsi a0, cycle, 0
+            0xC0007573, // csrrci a0, cycle, 0
+        ] {
+            let mut hart = hart();
+            assert!(emulate(&mut hart, bits), "{:#x}", bits);
+            assert_eq!(hart.x(10) as u64, hart.counters[0]);
+        }
+    }
+
+    #[test]
+    fn write_forms_are_not_emulated() {
+        for bits in [
+            0xC0001573, // csrrw a0, cycle, x0
+            0xC0059573, // csrrw a0, cycle, a1
+            0xC005A573, // csrrs a0, cycle, a1
+            0xC005B573, // csrrc a0, cycle, a1
+            0xC0005573, // csrrwi a0, cycle, 0
+            0xC000E573, // csrrsi a0, cycle, 1
+            0xC0059073, // csrw cycle, a1
+        ] {
+            let mut hart = hart();
+            assert!(!emulate(&mut hart, bits), "{:#x}", bits);
+            assert_eq!(hart.x(10), 0);
+            assert_eq!(hart.mepc(), 0x8020_0000);
+        }
+    }
+
+    #[test]
+    fn other_csrs_are_not_emulated() {
+        for bits in [
+            0x10002573, // csrr a0, sstatus
+            0xB0002573, // csrr a0, mcycle
+            0xC2002573, // csrr a0, 0xc20
+            0x00000013, // nop
+        ] {
+            let mut hart = hart();
+            assert!(!emulate(&mut hart, bits), "{:#x}", bits);
+        }
+        let mut hart = hart();
+        assert!(!emulate_counter_read(
+            &mut hart,
+            Instruction::Compressed(0x2573)
+        ));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn high_halves_do_not_exist_on_rv64() {
+        let mut hart = hart();
+        // csrr a0, cycleh
+        assert!(!emulate(&mut hart, 0xC8002573));
+    }
+
+    #[test]
+    fn counters_outside_policy_are_not_emulated() {
+        let mut hart = hart();
+        hart.policy = CounterPolicy {
+            direct: CounterPolicy::CYCLE | CounterPolicy::INSTRET,
+            emulated: CounterPolicy::TIME,
+        };
+        assert!(emulate(&mut hart, RDTIME_A0));
+        for bits in [RDCYCLE_A0, RDINSTRET_A0, RDHPM5_A0] {
+            let mut hart = hart.clone();
+            hart.set_mepc(0x8020_0000);
+            assert!(!emulate(&mut hart, bits), "{:#x}", bits);
+        }
+    }
+
+    #[test]
+    fn user_access_follows_scounteren() {
+        let mut hart = hart();
+        hart.mode = PrivilegeMode::User;
+        hart.scounteren = CounterPolicy::TIME;
+        assert!(emulate(&mut hart, RDTIME_A0));
+        assert!(!emulate(&mut hart, RDCYCLE_A0));
+        // the supervisor ignores scounteren for its own accesses
+        hart.mode = PrivilegeMode::Supervisor;
+        assert!(emulate(&mut hart, RDCYCLE_A0));
+    }
+
+    #[test]
+    fn mcounteren_holds_direct_counters() {
+        let policy = CounterPolicy {
+            direct: CounterPolicy::CYCLE | CounterPolicy::INSTRET,
+            emulated: !(CounterPolicy::CYCLE | CounterPolicy::INSTRET),
+        };
+        assert_eq!(policy.mcounteren(), 0b101);
+        assert_eq!(CounterPolicy::EMULATE_ALL.mcounteren(), 0);
+    }
+}
@@ -0,0 +1,256 @@
+//! RISC-V指令的长度判断和字段解码
+//!
+//! 指令按16位片段读取：先读低16位判断长度，是32位指令时再读下一个片段。
+//! 这样在页末尾的压缩指令不会因为多读两个字节而访问下一页。
+use crate::{FetchFault, Hart};
+
+/// 取到的一条指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 16位压缩指令（RVC）
+    Compressed(u16),
+    /// 32位指令
+    Standard(u32),
+    /// 48位以上的长指令，只保存最低的16位片段，这里不模拟
+    Long(u16),
+}
+
+impl Instruction {
+    /// 根据最低的16位片段解码指令长度
+    ///
+    /// 返回字节数；80位以上的指令长度在下一个片段中，这里返回`None`
+    pub fn length(parcel: u16) -> Option<usize> {
+        if parcel & 0b11 != 0b11 {
+            Some(2)
+        } else if parcel & 0b1_1100 != 0b1_1100 {
+            Some(4)
+        } else if parcel & 0b11_1111 == 0b01_1111 {
+            Some(6)
+        } else if parcel & 0b111_1111 == 0b011_1111 {
+            Some(8)
+        } else {
+            None
+        }
+    }
+
+    /// 指令占用的字节数，模拟完成后mepc需要增加这个值
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::Compressed(_) => 2,
+            Instruction::Standard(_) => 4,
+            Instruction::Long(parcel) => Self::length(parcel).unwrap_or(0),
+        }
+    }
+
+    /// 指令编码，长指令只有最低的16位
+    pub fn bits(&self) -> u32 {
+        match *self {
+            Instruction::Compressed(parcel) | Instruction::Long(parcel) => parcel as u32,
+            Instruction::Standard(bits) => bits,
+        }
+    }
+
+    // mtval中的指令编码，只在长度与内容一致时使用
+    fn from_mtval(mtval: usize) -> Option<Self> {
+        if mtval == 0 {
+            return None;
+        }
+        let parcel = mtval as u16;
+        match Self::length(parcel) {
+            Some(2) if mtval >> 16 == 0 => Some(Instruction::Compressed(parcel)),
+            Some(4) if mtval >> 16 >> 16 == 0 => Some(Instruction::Standard(mtval as u32)),
+            _ => None,
+        }
+    }
+}
+
+/// 读取mepc处陷入的指令
+///
+/// mtval中保存了指令编码时直接使用，不再访问内存；否则按16位片段读取。
+pub fn fetch_instruction(hart: &mut impl Hart) -> Result<Instruction, FetchFault> {
+    if let Some(ins) = Instruction::from_mtval(hart.mtval()) {
+        return Ok(ins);
+    }
+    let pc = hart.mepc();
+    let low = hart.fetch_u16(pc)?;
+    match Instruction::length(low) {
+        Some(2) => Ok(Instruction::Compressed(low)),
+        Some(4) => {
+            let high = hart.fetch_u16(pc.wrapping_add(2))?;
+            Ok(Instruction::Standard((high as u32) << 16 | low as u32))
+        }
+        _ => Ok(Instruction::Long(low)),
+    }
+}
+
+/// 模拟完成，跳过这条指令
+#[inline]
+pub fn skip_instruction(hart: &mut impl Hart, ins: Instruction) {
+    hart.set_mepc(hart.mepc().wrapping_add(ins.size()));
+}
+
+// 32位指令的字段
+
+#[inline]
+pub fn opcode(bits: u32) -> u32 {
+    bits & 0b111_1111
+}
+
+#[inline]
+pub fn rd(bits: u32) -> usize {
+    ((bits >> 7) & 0b1_1111) as usize
+}
+
+#[inline]
+pub fn funct3(bits: u32) -> u32 {
+    (bits >> 12) & 0b111
+}
+
+#[inline]
+pub fn rs1(bits: u32) -> usize {
+    ((bits >> 15) & 0b1_1111) as usize
+}
+
+#[inline]
+pub fn rs2(bits: u32) -> usize {
+    ((bits >> 20) & 0b1_1111) as usize
+}
+
+#[inline]
+pub fn funct7(bits: u32) -> u32 {
+    bits >> 25
+}
+
+/// SYSTEM指令中的CSR编号
+#[inline]
+pub fn csr(bits: u32) -> usize {
+    (bits >> 20) as usize
+}
+
+// 压缩指令的字段
+
+/// 压缩指令所在的象限，0到2
+#[inline]
+pub fn c_quadrant(parcel: u16) -> u16 {
+    parcel & 0b11
+}
+
+#[inline]
+pub fn c_funct3(parcel: u16) -> u16 {
+    parcel >> 13
+}
+
+/// 压缩指令中3位的寄存器编号rd'、rs1'和rs2'对应x8到x15
+#[inline]
+pub fn c_reg(field: u16) -> usize {
+    8 + (field & 0b111) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{c_reg, fetch_instruction, skip_instruction, Instruction};
+    use crate::mock::MockHart;
+    use crate::{FetchFault, Hart};
+
+    #[test]
+    fn length_from_low_bits() {
+        assert_eq!(Instruction::length(0x0001), Some(2)); // c.nop
+        assert_eq!(Instruction::length(0x8082), Some(2)); // c.ret
+        assert_eq!(Instruction::length(0x0000), Some(2)); // defined illegal instruction
+        assert_eq!(Instruction::length(0x0013), Some(4)); // nop
+        assert_eq!(Instruction::length(0x2573), Some(4)); // rdtime a0, low parcel
+        assert_eq!(Instruction::length(0x001f), Some(6));
+        assert_eq!(Instruction::length(0x003f), Some(8));
+        assert_eq!(Instruction::length(0x007f), None);
+    }
+
+    #[test]
+    fn fetch_standard_in_two_parcels() {
+        let mut hart = MockHart::new(0x1000);
+        hart.write_u16(0x1000, 0x2573);
+        hart.write_u16(0x1002, 0xC010);
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Standard(0xC0102573))
+        );
+    }
+
+    #[test]
+    fn compressed_at_end_of_page_reads_one_parcel() {
+        // only the two bytes before the page boundary are mapped
+        let mut hart = MockHart::new(0x1ffe);
+        hart.write_u16(0x1ffe, 0x8082);
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Compressed(0x8082))
+        );
+    }
+
+    #[test]
+    fn standard_across_page_boundary_faults_on_second_parcel() {
+        let mut hart = MockHart::new(0x1ffe);
+        hart.write_u16(0x1ffe, 0x2573);
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Err(FetchFault { vaddr: 0x2000 })
+        );
+    }
+
+    #[test]
+    fn mtval_is_used_without_fetching() {
+        // nothing is mapped, the instruction comes from mtval
+        let mut hart = MockHart::new(0x1000);
+        hart.mtval = 0xC0102573;
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Standard(0xC0102573))
+        );
+        hart.mtval = 0x8082;
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Compressed(0x8082))
+        );
+    }
+
+    #[test]
+    fn inconsistent_mtval_falls_back_to_fetch() {
+        let mut hart = MockHart::new(0x1000);
+        hart.write_u16(0x1000, 0x0001);
+        // a compressed encoding with garbage above it is not an instruction
+        hart.mtval = 0xffff_0001;
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Compressed(0x0001))
+        );
+        // a zero mtval holds no instruction
+        hart.mtval = 0;
+        assert_eq!(
+            fetch_instruction(&mut hart),
+            Ok(Instruction::Compressed(0x0001))
+        );
+    }
+
+    #[test]
+    fn long_instruction_reads_one_parcel() {
+        let mut hart = MockHart::new(0x1000);
+        hart.write_u16(0x1000, 0x001f);
+        let ins = fetch_instruction(&mut hart).unwrap();
+        assert_eq!(ins, Instruction::Long(0x001f));
+        assert_eq!(ins.size(), 6);
+    }
+
+    #[test]
+    fn skip_by_instruction_length() {
+        let mut hart = MockHart::new(0x1000);
+        skip_instruction(&mut hart, Instruction::Compressed(0x0001));
+        assert_eq!(hart.mepc(), 0x1002);
+        skip_instruction(&mut hart, Instruction::Standard(0x0000_0013));
+        assert_eq!(hart.mepc(), 0x1006);
+    }
+
+    #[test]
+    fn compressed_register_fields() {
+        assert_eq!(c_reg(0b000), 8);
+        assert_eq!(c_reg(0b111), 15);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::Hart;
+
+const A0: usize = 10;
+const A1: usize = 11;
+const A6: usize = 16;
+const A7: usize = 17;
+
+/// 读取SBI调用的参数：扩展号a7、功能号a6和参数a0-a5
+pub fn sbi_call_args(hart: &impl Hart) -> (usize, usize, [usize; 6]) {
+    let mut param = [0; 6];
+    for (i, value) in param.iter_mut().enumerate() {
+        *value = hart.x(A0 + i);
+    }
+    (hart.x(A7), hart.x(A6), param)
+}
+
+/// 写回SBI调用的返回值，并跳过ecall指令
+pub fn complete_sbi_call(hart: &mut impl Hart, error: usize, value: usize) {
+    hart.set_x(A0, error);
+    hart.set_x(A1, value);
+    hart.set_mepc(hart.mepc().wrapping_add(4));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_sbi_call, sbi_call_args};
+    use crate::mock::MockHart;
+    use crate::Hart;
+
+    #[test]
+    fn reads_extension_function_and_parameters() {
+        let mut hart = MockHart::new(0x1000);
+        for i in 10..18 {
+            hart.set_x(i, i * 100);
+        }
+        let (extension, function, param) = sbi_call_args(&hart);
+        assert_eq!(extension, 1700);
+        assert_eq!(function, 1600);
+        assert_eq!(param, [1000, 1100, 1200, 1300, 1400, 1500]);
+    }
+
+    #[test]
+    fn writes_back_a0_a1_and_skips_ecall() {
+        let mut hart = MockHart::new(0x8020_0010);
+        hart.set_x(12, 7);
+        complete_sbi_call(&mut hart, usize::MAX - 1, 0x55);
+        assert_eq!(hart.x(10), usize::MAX - 1);
+        assert_eq!(hart.x(11), 0x55);
+        // other registers are untouched
+        assert_eq!(hart.x(12), 7);
+        assert_eq!(hart.mepc(), 0x8020_0014);
+    }
+}
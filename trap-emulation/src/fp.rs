@@ -0,0 +1,125 @@
+//! 浮点寄存器的访问
+//!
+//! 上下文中只保存了整数寄存器。M层不使用浮点寄存器，陷入时f0-f31和fcsr仍然是S层的值，
+//! 需要时再直接读写。mstatus.FS为Off时S层没有打开浮点单元，这里的函数不访问浮点寄存器；
+//! 修改浮点寄存器后把FS设为Dirty，S层切换任务时才会保存它们。没有浮点单元的核（S7）上FS总是Off。
+use crate::Hart;
+
+/// 浮点单元的状态，也就是mstatus.FS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpState {
+    Off,
+    Initial,
+    Clean,
+    Dirty,
+}
+
+// fcsr只有低8位：frm和fflags
+const FCSR_MASK: u32 = 0xFF;
+
+/// 读取浮点寄存器f[index]，浮点单元关闭时返回`None`
+pub fn read_f(hart: &impl Hart, index: usize) -> Option<u64> {
+    assert!(index <= 31, "index should be valid float register");
+    if hart.fp_state() == FpState::Off {
+        return None;
+    }
+    Some(hart.f_unchecked(index))
+}
+
+/// 写入浮点寄存器f[index]，返回是否写入；浮点单元关闭时什么也不做
+pub fn write_f(hart: &mut impl Hart, index: usize, value: u64) -> bool {
+    assert!(index <= 31, "index should be valid float register");
+    if hart.fp_state() == FpState::Off {
+        return false;
+    }
+    hart.set_f_unchecked(index, value);
+    hart.set_fp_state(FpState::Dirty);
+    true
+}
+
+/// 读取fcsr，浮点单元关闭时返回`None`
+pub fn read_fcsr(hart: &impl Hart) -> Option<u32> {
+    if hart.fp_state() == FpState::Off {
+        return None;
+    }
+    Some(hart.fcsr_unchecked() & FCSR_MASK)
+}
+
+/// 写入fcsr，返回是否写入；浮点单元关闭时什么也不做
+pub fn write_fcsr(hart: &mut impl Hart, value: u32) -> bool {
+    if hart.fp_state() == FpState::Off {
+        return false;
+    }
+    hart.set_fcsr_unchecked(value & FCSR_MASK);
+    hart.set_fp_state(FpState::Dirty);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_f, read_fcsr, write_f, write_fcsr, FpState};
+    use crate::mock::MockHart;
+
+    fn hart(fp_state: FpState) -> MockHart {
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.fp_state = fp_state;
+        for (i, f) in hart.f.iter_mut().enumerate() {
+            *f = 0x4000_0000_0000_0000 | i as u64;
+        }
+        hart.fcsr = 0x21;
+        hart
+    }
+
+    #[test]
+    fn off_does_not_touch_registers() {
+        let mut hart = hart(FpState::Off);
+        assert_eq!(read_f(&hart, 3), None);
+        assert_eq!(read_fcsr(&hart), None);
+        assert!(!write_f(&mut hart, 3, 0));
+        assert!(!write_fcsr(&mut hart, 0));
+        assert_eq!(hart.f[3], 0x4000_0000_0000_0003);
+        assert_eq!(hart.fcsr, 0x21);
+        assert_eq!(hart.fp_state, FpState::Off);
+    }
+
+    #[test]
+    fn reads_keep_the_state() {
+        for state in [FpState::Initial, FpState::Clean, FpState::Dirty] {
+            let hart = hart(state);
+            assert_eq!(read_f(&hart, 0), Some(0x4000_0000_0000_0000));
+            assert_eq!(read_f(&hart, 31), Some(0x4000_0000_0000_001f));
+            assert_eq!(read_fcsr(&hart), Some(0x21));
+            assert_eq!(hart.fp_state, state);
+        }
+    }
+
+    #[test]
+    fn writes_mark_dirty() {
+        for state in [FpState::Initial, FpState::Clean, FpState::Dirty] {
+            let mut hart = hart(state);
+            assert!(write_f(&mut hart, 7, 0x1234));
+            assert_eq!(hart.f[7], 0x1234);
+            assert_eq!(hart.fp_state, FpState::Dirty);
+
+            let mut hart = self::hart(state);
+            assert!(write_fcsr(&mut hart, 0xE1));
+            assert_eq!(hart.fcsr, 0xE1);
+            assert_eq!(hart.fp_state, FpState::Dirty);
+        }
+    }
+
+    #[test]
+    fn fcsr_keeps_only_frm_and_fflags() {
+        let mut hart = hart(FpState::Clean);
+        assert!(write_fcsr(&mut hart, 0xFFFF_FF3F));
+        assert_eq!(hart.fcsr, 0x3F);
+        hart.fcsr = 0xABCD;
+        assert_eq!(read_fcsr(&hart), Some(0xCD));
+    }
+
+    #[test]
+    #[should_panic]
+    fn register_index_is_checked() {
+        read_f(&hart(FpState::Clean), 32);
+    }
+}
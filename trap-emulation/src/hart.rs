@@ -0,0 +1,51 @@
+use crate::{CounterPolicy, FpState};
+
+/// 陷入之前的特权级，也就是mstatus.MPP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivilegeMode {
+    User,
+    Supervisor,
+    Machine,
+}
+
+/// 读取陷入前地址空间中的内存时出现了异常
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchFault {
+    pub vaddr: usize,
+}
+
+/// 模拟逻辑看到的核：陷入前的通用寄存器、陷入相关的CSR、S态内存和时钟
+pub trait Hart {
+    /// 读第index个通用寄存器，x0总是0
+    fn x(&self, index: usize) -> usize;
+    /// 写第index个通用寄存器，写x0没有作用
+    fn set_x(&mut self, index: usize, value: usize);
+    fn mepc(&self) -> usize;
+    fn set_mepc(&mut self, mepc: usize);
+    /// 陷入之前的特权级
+    fn previous_mode(&self) -> PrivilegeMode;
+    /// 陷入时mtval的值；非法指令异常时可能是指令本身，也可能是0
+    fn mtval(&self) -> usize;
+    /// 用陷入前的地址空间读取16位的指令片段
+    fn fetch_u16(&mut self, vaddr: usize) -> Result<u16, FetchFault>;
+    /// 当前的mtime值
+    fn mtime(&self) -> u64;
+    /// M层计数器的值：0是mcycle，2是minstret，3到31是mhpmcounter3到mhpmcounter31
+    fn machine_counter(&self, index: usize) -> u64;
+    /// S层设置的scounteren，决定U层能读取哪些计数器
+    fn scounteren(&self) -> u32;
+    /// 这个核上计数器的开放方式
+    fn counter_policy(&self) -> CounterPolicy;
+    /// 陷入前的mstatus.FS；没有浮点单元的核总是Off
+    fn fp_state(&self) -> FpState;
+    /// 修改返回S层时的mstatus.FS
+    fn set_fp_state(&mut self, state: FpState);
+    /// 读取浮点寄存器，只能在FS不为Off时调用
+    fn f_unchecked(&self, index: usize) -> u64;
+    /// 写入浮点寄存器，只能在FS不为Off时调用
+    fn set_f_unchecked(&mut self, index: usize, value: u64);
+    /// 读取fcsr，只能在FS不为Off时调用
+    fn fcsr_unchecked(&self) -> u32;
+    /// 写入fcsr，只能在FS不为Off时调用
+    fn set_fcsr_unchecked(&mut self, value: u32);
+}
@@ -0,0 +1,163 @@
+use crate::decode::fetch_instruction;
+use crate::{emulate_counter_read, FetchFault, Hart, PrivilegeMode};
+
+/// 非法指令异常的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalInstruction {
+    /// 指令已经模拟完成，mepc已经指向下一条指令
+    Emulated,
+    /// 不能模拟的指令，应当把异常转交给S层处理
+    Transfer,
+    /// M层自身出现的非法指令，附带指令编码
+    Fail(u32),
+    /// M层自身出现的非法指令，而且读取指令时出错
+    FetchFailed(FetchFault),
+}
+
+/// 判断非法指令异常是否来自S层或U层，这时应当转交给S层处理
+#[inline]
+pub fn should_transfer_trap(hart: &impl Hart) -> bool {
+    hart.previous_mode() != PrivilegeMode::Machine
+}
+
+/// 读取mepc处的指令，尝试模拟；不能模拟时决定异常的去向
+pub fn handle_illegal_instruction(hart: &mut impl Hart) -> IllegalInstruction {
+    let ins = match fetch_instruction(hart) {
+        Ok(ins) => ins,
+        // 读不到指令，也就无从模拟，交给S层按非法指令处理
+        Err(_) if should_transfer_trap(hart) => return IllegalInstruction::Transfer,
+        Err(fault) => return IllegalInstruction::FetchFailed(fault),
+    };
+    if emulate_counter_read(hart, ins) {
+        return IllegalInstruction::Emulated;
+    }
+    #[cfg(feature = "bitmanip")]
+    if crate::emulate_bitmanip(hart, ins) {
+        return IllegalInstruction::Emulated;
+    }
+    if should_transfer_trap(hart) {
+        IllegalInstruction::Transfer
+    } else {
+        IllegalInstruction::Fail(ins.bits())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_illegal_instruction, IllegalInstruction};
+    use crate::mock::MockHart;
+    use crate::{CounterPolicy, FetchFault, Hart, PrivilegeMode};
+
+    const RDTIME_A0: u32 = 0xC0102573;
+    const CSRW_MCYCLE_A0: u32 = 0xB0051073;
+
+    #[test]
+    fn rdtime_from_supervisor_is_emulated() {
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.mtime = 1000;
+        hart.write_u32(0x8020_0000, RDTIME_A0);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Emulated
+        );
+        assert_eq!(hart.x(10), 1000);
+        assert_eq!(hart.mepc(), 0x8020_0004);
+    }
+
+    #[test]
+    fn rdtime_from_user_is_emulated() {
+        let mut hart = MockHart::new(0x1_0000);
+        hart.mode = PrivilegeMode::User;
+        hart.scounteren = CounterPolicy::TIME;
+        hart.mtime = 7;
+        hart.write_u32(0x1_0000, RDTIME_A0);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Emulated
+        );
+        assert_eq!(hart.x(10), 7);
+    }
+
+    #[test]
+    fn rdtime_from_user_without_scounteren_is_transferred() {
+        let mut hart = MockHart::new(0x1_0000);
+        hart.mode = PrivilegeMode::User;
+        hart.write_u32(0x1_0000, RDTIME_A0);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Transfer
+        );
+        assert_eq!(hart.mepc(), 0x1_0000);
+    }
+
+    #[test]
+    fn unknown_instruction_from_supervisor_is_transferred() {
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.write_u32(0x8020_0000, CSRW_MCYCLE_A0);
+        hart.set_x(10, 0x55);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Transfer
+        );
+        // nothing is written back, the supervisor sees the trapping instruction
+        assert_eq!(hart.x(10), 0x55);
+        assert_eq!(hart.mepc(), 0x8020_0000);
+    }
+
+    #[test]
+    fn unknown_instruction_from_machine_fails() {
+        let mut hart = MockHart::new(0x8000_0100);
+        hart.mode = PrivilegeMode::Machine;
+        hart.write_u32(0x8000_0100, CSRW_MCYCLE_A0);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Fail(CSRW_MCYCLE_A0)
+        );
+        assert_eq!(hart.mepc(), 0x8000_0100);
+    }
+
+    #[test]
+    fn rdtime_from_mtval_is_emulated() {
+        // the instruction is not mapped, the hart reports it in mtval
+        let mut hart = MockHart::new(0x8020_0000);
+        hart.mtval = RDTIME_A0 as usize;
+        hart.mtime = 99;
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Emulated
+        );
+        assert_eq!(hart.x(10), 99);
+        assert_eq!(hart.mepc(), 0x8020_0004);
+    }
+
+    #[test]
+    fn compressed_instruction_is_transferred_unchanged() {
+        let mut hart = MockHart::new(0x8020_0ffe);
+        // c.ebreak-like parcel at the end of the only mapped page
+        hart.write_u16(0x8020_0ffe, 0x9002);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Transfer
+        );
+        assert_eq!(hart.mepc(), 0x8020_0ffe);
+    }
+
+    #[test]
+    fn fetch_fault_from_supervisor_is_transferred() {
+        let mut hart = MockHart::new(0xdead_0000);
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::Transfer
+        );
+    }
+
+    #[test]
+    fn fetch_fault_from_machine_fails() {
+        let mut hart = MockHart::new(0xdead_0000);
+        hart.mode = PrivilegeMode::Machine;
+        assert_eq!(
+            handle_illegal_instruction(&mut hart),
+            IllegalInstruction::FetchFailed(FetchFault { vaddr: 0xdead_0000 })
+        );
+    }
+}
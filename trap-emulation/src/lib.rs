@@ -0,0 +1,25 @@
+//! 陷入处理中与硬件无关的模拟逻辑
+//!
+//! 这里的代码只通过[`Hart`]访问通用寄存器、CSR、S态内存和时钟，不直接读写硬件，
+//! 可以在主机上用`cargo test -p trap-emulation`测试；固件为真实的核实现[`Hart`]。
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "bitmanip")]
+mod bitmanip;
+mod counter;
+pub mod decode;
+mod ecall;
+pub mod fp;
+mod hart;
+mod illegal;
+#[cfg(test)]
+mod mock;
+
+#[cfg(feature = "bitmanip")]
+pub use bitmanip::emulate_bitmanip;
+pub use counter::{emulate_counter_read, CounterPolicy};
+pub use decode::Instruction;
+pub use ecall::{complete_sbi_call, sbi_call_args};
+pub use fp::FpState;
+pub use hart::{FetchFault, Hart, PrivilegeMode};
+pub use illegal::{handle_illegal_instruction, should_transfer_trap, IllegalInstruction};
@@ -0,0 +1,114 @@
+//! 测试用的模拟核
+use crate::{CounterPolicy, FetchFault, FpState, Hart, PrivilegeMode};
+use std::collections::BTreeMap;
+
+#[derive(Clone)]
+pub struct MockHart {
+    x: [usize; 32],
+    mepc: usize,
+    pub mode: PrivilegeMode,
+    pub mtime: u64,
+    pub mtval: usize,
+    pub counters: [u64; 32],
+    pub scounteren: u32,
+    pub policy: CounterPolicy,
+    pub fp_state: FpState,
+    pub f: [u64; 32],
+    pub fcsr: u32,
+    // 按字节保存的S态内存，没有写过的地址读取时出错
+    memory: BTreeMap<usize, u8>,
+}
+
+impl MockHart {
+    pub fn new(mepc: usize) -> Self {
+        MockHart {
+            x: [0; 32],
+            mepc,
+            mode: PrivilegeMode::Supervisor,
+            mtime: 0,
+            mtval: 0,
+            counters: [0; 32],
+            scounteren: 0,
+            policy: CounterPolicy::EMULATE_ALL,
+            fp_state: FpState::Off,
+            f: [0; 32],
+            fcsr: 0,
+            memory: BTreeMap::new(),
+        }
+    }
+
+    pub fn write_u16(&mut self, vaddr: usize, value: u16) {
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.memory.insert(vaddr + i, byte);
+        }
+    }
+
+    pub fn write_u32(&mut self, vaddr: usize, value: u32) {
+        self.write_u16(vaddr, value as u16);
+        self.write_u16(vaddr + 2, (value >> 16) as u16);
+    }
+}
+
+impl Hart for MockHart {
+    fn x(&self, index: usize) -> usize {
+        self.x[index]
+    }
+    fn set_x(&mut self, index: usize, value: usize) {
+        if index != 0 {
+            self.x[index] = value;
+        }
+    }
+    fn mepc(&self) -> usize {
+        self.mepc
+    }
+    fn set_mepc(&mut self, mepc: usize) {
+        self.mepc = mepc;
+    }
+    fn previous_mode(&self) -> PrivilegeMode {
+        self.mode
+    }
+    fn mtval(&self) -> usize {
+        self.mtval
+    }
+    fn fetch_u16(&mut self, vaddr: usize) -> Result<u16, FetchFault> {
+        let mut bytes = [0; 2];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = *self.memory.get(&(vaddr + i)).ok_or(FetchFault { vaddr })?;
+        }
+        Ok(u16::from_le_bytes(bytes))
+    }
+    fn mtime(&self) -> u64 {
+        self.mtime
+    }
+    fn machine_counter(&self, index: usize) -> u64 {
+        self.counters[index]
+    }
+    fn scounteren(&self) -> u32 {
+        self.scounteren
+    }
+    fn counter_policy(&self) -> CounterPolicy {
+        self.policy
+    }
+    fn fp_state(&self) -> FpState {
+        self.fp_state
+    }
+    fn set_fp_state(&mut self, state: FpState) {
+        self.fp_state = state;
+    }
+    fn f_unchecked(&self, index: usize) -> u64 {
+        assert_ne!(self.fp_state, FpState::Off);
+        self.f[index]
+    }
+    fn set_f_unchecked(&mut self, index: usize, value: u64) {
+        assert_ne!(self.fp_state, FpState::Off);
+        self.f[index] = value;
+    }
+    fn fcsr_unchecked(&self) -> u32 {
+        assert_ne!(self.fp_state, FpState::Off);
+        self.fcsr
+    }
+    fn set_fcsr_unchecked(&mut self, value: u32) {
+        assert_ne!(self.fp_state, FpState::Off);
+        self.fcsr = value;
+    }
+}
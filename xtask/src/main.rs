@@ -1,8 +1,13 @@
+mod qemu;
+mod symbolize;
+
 use std::fmt;
 use std::{
-    env,
+    env, fs,
+    io::{self, BufReader},
     path::{Path, PathBuf},
     process::{self, Command},
+    time::Duration,
 };
 
 use clap::{clap_app, crate_authors, crate_description, crate_version};
@@ -10,6 +15,7 @@ use clap::{clap_app, crate_authors, crate_description, crate_version};
 #[derive(Debug)]
 struct XtaskEnv {
     compile_mode: CompileMode,
+    platform: Platform,
 }
 
 #[derive(Debug)]
@@ -27,6 +33,36 @@ impl fmt::Display for CompileMode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    HifiveUnmatched,
+    QemuSifiveU,
+}
+
+impl Platform {
+    fn from_arg(arg: Option<&str>) -> Platform {
+        match arg {
+            None | Some("hifive-unmatched") => Platform::HifiveUnmatched,
+            Some("qemu-sifive-u") => Platform::QemuSifiveU,
+            Some(other) => {
+                eprintln!(
+                    "unknown platform '{}', may be 'hifive-unmatched' or 'qemu-sifive-u'",
+                    other
+                );
+                process::exit(1);
+            }
+        }
+    }
+
+    // 选择平台的cargo特性，默认平台不需要特性
+    fn feature(&self) -> Option<&'static str> {
+        match self {
+            Platform::HifiveUnmatched => None,
+            Platform::QemuSifiveU => Some("qemu-sifive-u"),
+        }
+    }
+}
+
 const DEFAULT_TARGET: &'static str = "riscv64imac-unknown-none-elf";
 
 fn main() {
@@ -37,10 +73,12 @@ fn main() {
         (@subcommand make =>
             (about: "Build project")
             (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg platform: --platform +takes_value "Target platform, 'hifive-unmatched' (default) or 'qemu-sifive-u'")
         )
         (@subcommand asm =>
             (about: "View asm code for project")
             (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg platform: --platform +takes_value "Target platform, 'hifive-unmatched' (default) or 'qemu-sifive-u'")
         )
         (@subcommand image =>
             (about: "Build SD card partition image")
@@ -50,23 +88,55 @@ fn main() {
         (@subcommand gdb =>
             (about: "Run GDB debugger")
         )
+        (@subcommand qemu =>
+            (about: "Run RustSBI and a payload in QEMU sifive_u with the serial console on stdio")
+            (@arg PAYLOAD: "Payload binary to boot, defaults to 'test-kernel'")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+        )
+        (@subcommand test =>
+            (about: "Run test-kernel in QEMU sifive_u headless and check its result")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg timeout: --timeout +takes_value "Seconds to wait for a test result, default 60")
+        )
+        (@subcommand fuzz =>
+            (about: "Run the SBI call fuzzer in QEMU sifive_u headless")
+            (@arg seed: --seed +takes_value "Seed of the pseudo-random calls, decimal or 0x-prefixed hex; random if omitted")
+            (@arg calls: --calls +takes_value "Number of calls to make, default 100000")
+            (@arg verbose: --verbose "Print every call before it is made")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg timeout: --timeout +takes_value "Seconds to wait for the fuzzer result, default 600")
+        )
+        (@subcommand symbolize =>
+            (about: "Annotate addresses in a serial log with function names and source lines")
+            (@arg LOG: "Captured serial log file; reads from stdin if omitted")
+            (@arg release: --release "Use ELF files built in release mode")
+        )
     )
     .get_matches();
     let mut xtask_env = XtaskEnv {
         compile_mode: CompileMode::Debug,
+        platform: Platform::HifiveUnmatched,
     };
     if let Some(matches) = matches.subcommand_matches("make") {
         if matches.is_present("release") {
             xtask_env.compile_mode = CompileMode::Release;
         }
-        eprintln!("xtask make: mode: {:?}", xtask_env.compile_mode);
+        xtask_env.platform = Platform::from_arg(matches.value_of("platform"));
+        eprintln!(
+            "xtask make: mode: {:?}, platform: {:?}",
+            xtask_env.compile_mode, xtask_env.platform
+        );
         xtask_build_sbi(&xtask_env);
         xtask_binary_sbi(&xtask_env);
     } else if let Some(matches) = matches.subcommand_matches("asm") {
         if matches.is_present("release") {
             xtask_env.compile_mode = CompileMode::Release;
         }
-        eprintln!("xtask asm: mode: {:?}", xtask_env.compile_mode);
+        xtask_env.platform = Platform::from_arg(matches.value_of("platform"));
+        eprintln!(
+            "xtask asm: mode: {:?}, platform: {:?}",
+            xtask_env.compile_mode, xtask_env.platform
+        );
         xtask_build_sbi(&xtask_env);
         xtask_asm_sbi(&xtask_env);
     } else if let Some(matches) = matches.subcommand_matches("image") {
@@ -88,6 +158,84 @@ fn main() {
         xtask_build_sbi(&xtask_env);
         xtask_binary_sbi(&xtask_env);
         xtask_unmatched_gdb(&xtask_env);
+    } else if let Some(matches) = matches.subcommand_matches("qemu") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        xtask_env.platform = Platform::QemuSifiveU;
+        eprintln!("xtask qemu: mode: {:?}", xtask_env.compile_mode);
+        xtask_build_sbi(&xtask_env);
+        let payload = match matches.value_of("PAYLOAD") {
+            None | Some("test-kernel") => {
+                xtask_build_test_kernel(&xtask_env);
+                xtask_binary_test_kernel(&xtask_env);
+                dist_dir(&xtask_env).join("test-kernel.bin")
+            }
+            Some(path) => PathBuf::from(path),
+        };
+        xtask_qemu(&xtask_env, &payload);
+    } else if let Some(matches) = matches.subcommand_matches("test") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        xtask_env.platform = Platform::QemuSifiveU;
+        let timeout = match matches.value_of("timeout").map(str::parse::<u64>) {
+            None => Duration::from_secs(60),
+            Some(Ok(secs)) => Duration::from_secs(secs),
+            Some(Err(e)) => {
+                eprintln!("invalid timeout: {}", e);
+                process::exit(1);
+            }
+        };
+        eprintln!("xtask test: mode: {:?}", xtask_env.compile_mode);
+        xtask_build_sbi(&xtask_env);
+        xtask_build_test_kernel(&xtask_env);
+        xtask_binary_test_kernel(&xtask_env);
+        xtask_test(&xtask_env, "test-kernel", timeout);
+    } else if let Some(matches) = matches.subcommand_matches("fuzz") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        xtask_env.platform = Platform::QemuSifiveU;
+        let timeout = match matches.value_of("timeout").map(str::parse::<u64>) {
+            None => Duration::from_secs(600),
+            Some(Ok(secs)) => Duration::from_secs(secs),
+            Some(Err(e)) => {
+                eprintln!("invalid timeout: {}", e);
+                process::exit(1);
+            }
+        };
+        let seed = match matches.value_of("seed").map(parse_seed) {
+            None => random_seed(),
+            Some(Ok(seed)) => seed,
+            Some(Err(e)) => {
+                eprintln!("invalid seed: {}", e);
+                process::exit(1);
+            }
+        };
+        let calls = match matches.value_of("calls").map(str::parse::<u64>) {
+            None => None,
+            Some(Ok(calls)) => Some(calls),
+            Some(Err(e)) => {
+                eprintln!("invalid number of calls: {}", e);
+                process::exit(1);
+            }
+        };
+        // 打印种子，失败时可以用同一个种子重现
+        eprintln!(
+            "xtask fuzz: mode: {:?}, seed: {:#x}",
+            xtask_env.compile_mode, seed
+        );
+        xtask_build_sbi(&xtask_env);
+        xtask_build_sbi_fuzzer(&xtask_env, seed, calls, matches.is_present("verbose"));
+        xtask_binary_sbi_fuzzer(&xtask_env);
+        xtask_test(&xtask_env, "sbi-fuzzer", timeout);
+    } else if let Some(matches) = matches.subcommand_matches("symbolize") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        eprintln!("xtask symbolize: mode: {:?}", xtask_env.compile_mode);
+        xtask_symbolize(&xtask_env, matches.value_of("LOG"));
     } else {
         eprintln!("Use `cargo make` to build, `cargo xtask --help` for help")
     }
@@ -106,6 +254,9 @@ fn xtask_build_sbi(xtask_env: &XtaskEnv) {
     }
     command.args(&["--package", "rustsbi-hifive-unmatched"]);
     command.args(&["--target", DEFAULT_TARGET]);
+    if let Some(feature) = xtask_env.platform.feature() {
+        command.args(["--features", feature]);
+    }
     let status = command.status().unwrap();
     if !status.success() {
         eprintln!("cargo build failed");
@@ -161,6 +312,85 @@ fn xtask_unmatched_gdb(xtask_env: &XtaskEnv) {
     }
 }
 
+fn xtask_qemu(xtask_env: &XtaskEnv, payload: &Path) {
+    let firmware = dist_dir(xtask_env).join("rustsbi-hifive-unmatched");
+    let status = qemu::qemu_command(&firmware, payload)
+        .status()
+        .expect("run qemu");
+
+    if !status.success() {
+        eprintln!("qemu failed with status {}", status);
+        process::exit(status.code().unwrap_or(1));
+    }
+}
+
+// 负载可以是test-kernel或sbi-fuzzer
+fn xtask_test(xtask_env: &XtaskEnv, payload: &str, timeout: Duration) {
+    let firmware = dist_dir(xtask_env).join("rustsbi-hifive-unmatched");
+    let payload_bin = dist_dir(xtask_env).join(format!("{}.bin", payload));
+    let command = qemu::qemu_command(&firmware, &payload_bin);
+    match qemu::run_test(command, timeout).expect("run qemu") {
+        qemu::Verdict::Success => eprintln!("xtask test: {} SUCCESS", payload),
+        qemu::Verdict::Failed(line) => {
+            eprintln!("xtask test: {} FAILED: {}", payload, line);
+            process::exit(1);
+        }
+        qemu::Verdict::Timeout => {
+            eprintln!(
+                "xtask test: no test result after {} seconds, machine hangs",
+                timeout.as_secs()
+            );
+            process::exit(1);
+        }
+        qemu::Verdict::Exited => {
+            eprintln!("xtask test: qemu exited before test result");
+            process::exit(1);
+        }
+    }
+}
+
+fn xtask_symbolize(xtask_env: &XtaskEnv, log: Option<&str>) {
+    let load = |name: &str| {
+        let path = dist_dir(xtask_env).join(name);
+        if !path.exists() {
+            return None;
+        }
+        match symbolize::Symbolizer::load(name, &path) {
+            Ok(symbolizer) => Some(symbolizer),
+            Err(e) => {
+                eprintln!("xtask symbolize: {}", e);
+                None
+            }
+        }
+    };
+    let rustsbi = load("rustsbi-hifive-unmatched");
+    let test_kernel = load("test-kernel");
+    if rustsbi.is_none() && test_kernel.is_none() {
+        eprintln!(
+            "no ELF file found in {}, build the project first",
+            dist_dir(xtask_env).display()
+        );
+        process::exit(1);
+    }
+    let stdin = io::stdin();
+    let mut input: Box<dyn io::BufRead> = match log {
+        Some(path) => match fs::File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("xtask symbolize: open {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => Box::new(stdin.lock()),
+    };
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    if let Err(e) = symbolize::symbolize(rustsbi, test_kernel, &mut input, &mut output) {
+        eprintln!("symbolize failed: {}", e);
+        process::exit(1);
+    }
+}
+
 fn xtask_sd_image(xtask_env: &XtaskEnv) {
     let status = find_mkimage()
         .expect("find mkimage tool")
@@ -214,6 +444,71 @@ fn xtask_binary_test_kernel(xtask_env: &XtaskEnv) {
     }
 }
 
+fn xtask_build_sbi_fuzzer(xtask_env: &XtaskEnv, seed: u64, calls: Option<u64>, verbose: bool) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut command = Command::new(cargo);
+    command.current_dir(project_root().join("sbi-fuzzer"));
+    command.arg("build");
+    match xtask_env.compile_mode {
+        CompileMode::Debug => {}
+        CompileMode::Release => {
+            command.arg("--release");
+        }
+    }
+    command.args(["--package", "sbi-fuzzer"]);
+    command.args(["--target", DEFAULT_TARGET]);
+    // 参数在编译时写入负载，见sbi-fuzzer/src/main.rs
+    command.env("SBI_FUZZ_SEED", format!("{:#x}", seed));
+    match calls {
+        Some(calls) => command.env("SBI_FUZZ_CALLS", calls.to_string()),
+        None => command.env_remove("SBI_FUZZ_CALLS"),
+    };
+    if verbose {
+        command.env("SBI_FUZZ_VERBOSE", "1");
+    } else {
+        command.env_remove("SBI_FUZZ_VERBOSE");
+    }
+    let status = command.status().unwrap();
+    if !status.success() {
+        eprintln!("cargo build failed");
+        process::exit(1);
+    }
+}
+
+fn xtask_binary_sbi_fuzzer(xtask_env: &XtaskEnv) {
+    let objcopy = "rust-objcopy";
+    let status = Command::new(objcopy)
+        .current_dir(dist_dir(xtask_env))
+        .arg("sbi-fuzzer")
+        .arg("--binary-architecture=riscv64")
+        .arg("--strip-all")
+        .args(["-O", "binary", "sbi-fuzzer.bin"])
+        .status()
+        .unwrap();
+
+    if !status.success() {
+        eprintln!("objcopy binary failed");
+        process::exit(1);
+    }
+}
+
+// 种子可以是十进制数，也可以是0x开头的十六进制数
+fn parse_seed(seed: &str) -> Result<u64, std::num::ParseIntError> {
+    match seed.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => seed.parse(),
+    }
+}
+
+// 没有指定种子时，用当前时间生成一个
+fn random_seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() ^ (now.subsec_nanos() as u64) << 32
+}
+
 fn xtask_sd_image_test_kernel(xtask_env: &XtaskEnv) {
     let status = find_mkimage()
         .expect("find mkimage tool")
@@ -0,0 +1,109 @@
+// 在QEMU的sifive_u机器中运行固件和负载，以及判定test-kernel的测试结果
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 和HiFive Unmatched主板一样，使用五个核
+const QEMU_SMP: &str = "5";
+
+pub fn qemu_command(firmware: &Path, payload: &Path) -> Command {
+    // 可以用QEMU环境变量指定QEMU的路径
+    let qemu = env::var("QEMU").unwrap_or_else(|_| "qemu-system-riscv64".to_string());
+    let mut command = Command::new(qemu);
+    command.args(["-machine", "sifive_u"]);
+    command.args(["-smp", QEMU_SMP]);
+    command.arg("-nographic");
+    command.arg("-bios").arg(firmware);
+    command.arg("-kernel").arg(payload);
+    command
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Success,
+    Failed(String),
+    Timeout,
+    Exited,
+}
+
+// test-kernel最后输出一行`test-kernel-result: passed=N failed=N skipped=N`，
+// sbi-fuzzer输出`sbi-fuzzer-result: seed=S calls=N skipped=N failed=N`，只按这两种行判定结果；
+// 每项检查输出的`<< [PASS] ...`和`!! [FAIL] ...`中也可能出现SUCCESS或FAILED，不能据此结束运行。
+// 负载panic时输出没有failed字段的结果行，固件panic时输出`[rustsbi-panic]`，都算失败
+fn parse_line(line: &str) -> Option<Verdict> {
+    let line = line.trim();
+    if let Some(result) = RESULT_PREFIXES
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+    {
+        Some(parse_result(line, result))
+    } else if line.starts_with("[rustsbi-panic]") {
+        Some(Verdict::Failed(line.to_string()))
+    } else {
+        None
+    }
+}
+
+const RESULT_PREFIXES: [&str; 2] = ["test-kernel-result:", "sbi-fuzzer-result:"];
+
+fn parse_result(line: &str, result: &str) -> Verdict {
+    let failed = result.split_whitespace().find_map(|field| {
+        field
+            .strip_prefix("failed=")
+            .and_then(|count| count.parse::<usize>().ok())
+    });
+    match failed {
+        Some(0) => Verdict::Success,
+        _ => Verdict::Failed(line.to_string()),
+    }
+}
+
+/// 不带交互地运行QEMU，把串口输出原样打印出来，直到得出测试结果或者超时
+pub fn run_test(mut command: Command, timeout: Duration) -> io::Result<Verdict> {
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().expect("take qemu stdout");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // 串口输出中可能有不是UTF-8的字节，按字节读取，不让一个坏字节提前结束读取
+        let mut stdout = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match stdout.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = Instant::now() + timeout;
+    let verdict = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(remaining) {
+            Ok(line) => {
+                println!("{}", line);
+                if let Some(verdict) = parse_line(&line) {
+                    break verdict;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => break Verdict::Timeout,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break Verdict::Exited,
+        }
+    };
+    io::stdout().flush()?;
+    // 得出结果后不再等待关机，直接结束QEMU
+    child.kill().ok();
+    child.wait()?;
+    Ok(verdict)
+}
@@ -0,0 +1,177 @@
+// 把串口日志中的地址翻译成函数名和源码行号，不依赖外部的riscv binutils
+
+use addr2line::object::{self, Object, ObjectSection, SectionKind};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+type Context = addr2line::Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>;
+
+/// 一个可以用于查询地址的ELF文件
+pub struct Symbolizer {
+    name: String,
+    context: Context,
+    symbols: object::SymbolMap<object::SymbolMapName<'static>>,
+    text_ranges: Vec<(u64, u64)>,
+}
+
+impl Symbolizer {
+    pub fn load(name: &str, path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("read {}: {}", path.display(), e))?;
+        // 符号表的名称借用了文件内容，文件在整个xtask运行期间都需要，直接泄漏即可
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        let file =
+            object::File::parse(data).map_err(|e| format!("parse {}: {}", path.display(), e))?;
+        let text_ranges = file
+            .sections()
+            .filter(|section| section.kind() == SectionKind::Text)
+            .map(|section| (section.address(), section.address() + section.size()))
+            .collect();
+        let symbols = file.symbol_map();
+        let context = Context::new(&file)
+            .map_err(|e| format!("load debug info of {}: {}", path.display(), e))?;
+        Ok(Symbolizer {
+            name: name.to_string(),
+            context,
+            symbols,
+            text_ranges,
+        })
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        self.text_ranges
+            .iter()
+            .any(|&(start, end)| addr >= start && addr < end)
+    }
+
+    // 返回地址对应的所有帧，内联函数在前，最外层函数在后
+    fn resolve(&self, addr: u64) -> Vec<String> {
+        let mut ans = Vec::new();
+        if let Ok(mut frames) = self.context.find_frames(addr) {
+            while let Ok(Some(frame)) = frames.next() {
+                let function = match &frame.function {
+                    Some(function) => function
+                        .demangle()
+                        .unwrap_or(Cow::Borrowed("??"))
+                        .into_owned(),
+                    None => self.symbol_name(addr).unwrap_or_else(|| "??".to_string()),
+                };
+                let location = match &frame.location {
+                    Some(location) => format!(
+                        "{}:{}",
+                        location.file.unwrap_or("??"),
+                        location.line.unwrap_or(0)
+                    ),
+                    None => "??:0".to_string(),
+                };
+                ans.push(format!("{} at {}", function, location));
+            }
+        }
+        if ans.is_empty() {
+            // 没有调试信息（比如release模式），退回到符号表
+            if let Some(name) = self.symbol_name(addr) {
+                ans.push(name);
+            }
+        }
+        ans
+    }
+
+    fn symbol_name(&self, addr: u64) -> Option<String> {
+        let symbol = self.symbols.get(addr)?;
+        let name = addr2line::demangle_auto(Cow::Borrowed(symbol.name()), None);
+        Some(format!("{}+{:#x}", name, addr - symbol.address()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineSource {
+    RustSbi,
+    TestKernel,
+    Unknown,
+}
+
+// 根据日志行的前缀判断它来自哪个程序
+fn line_source(line: &str) -> LineSource {
+    let line = line.trim_start();
+    if line.starts_with("[rustsbi") || line.starts_with("rustsbi:") {
+        LineSource::RustSbi
+    } else if line.starts_with("<<") || line.starts_with(">>") || line.starts_with("!!") {
+        LineSource::TestKernel
+    } else {
+        LineSource::Unknown
+    }
+}
+
+// 找出一行中所有可能是地址的十六进制数，包括带0x前缀的数，以及`{:x?}`打印的至少8位的数
+fn find_addresses(line: &str) -> Vec<u64> {
+    let mut ans = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if i > 0 && bytes[i - 1].is_ascii_alphanumeric() {
+            i += 1;
+            continue;
+        }
+        let (prefixed, start) = if bytes[i..].starts_with(b"0x") {
+            (true, i + 2)
+        } else {
+            (false, i)
+        };
+        let mut end = start;
+        while end < bytes.len() && bytes[end].is_ascii_hexdigit() {
+            end += 1;
+        }
+        let terminated = end == bytes.len() || !bytes[end].is_ascii_alphanumeric();
+        let digits = end - start;
+        if terminated && digits <= 16 && (digits >= 8 || (prefixed && digits > 0)) {
+            if let Ok(addr) = u64::from_str_radix(&line[start..end], 16) {
+                if !ans.contains(&addr) {
+                    ans.push(addr);
+                }
+            }
+        }
+        i = end.max(i + 1);
+    }
+    ans
+}
+
+pub fn symbolize(
+    rustsbi: Option<Symbolizer>,
+    test_kernel: Option<Symbolizer>,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    // 串口日志中常有不是UTF-8的字节，按字节读取，不让一个坏字节中断整个日志的处理
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if input.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let text = line.trim_end_matches(&['\r', '\n'][..]);
+        writeln!(output, "{}", text)?;
+        // 优先使用日志行来源对应的ELF，然后再尝试另一个
+        let candidates = match line_source(text) {
+            LineSource::TestKernel => [&test_kernel, &rustsbi],
+            LineSource::RustSbi | LineSource::Unknown => [&rustsbi, &test_kernel],
+        };
+        for addr in find_addresses(text) {
+            let found = candidates
+                .iter()
+                .filter_map(|elf| elf.as_ref())
+                .find(|elf| elf.contains(addr));
+            if let Some(elf) = found {
+                for (i, frame) in elf.resolve(addr).iter().enumerate() {
+                    if i == 0 {
+                        writeln!(output, "    {:#x} [{}] {}", addr, elf.name, frame)?;
+                    } else {
+                        writeln!(output, "        (inlined by) {}", frame)?;
+                    }
+                }
+            }
+        }
+    }
+    output.flush()
+}